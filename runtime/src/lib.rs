@@ -219,7 +219,8 @@ pub type UncheckedExtrinsic = generic::UncheckedMortalExtrinsic<Address, Nonce,
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Nonce, Call>;
 /// Executive: handles dispatch to the various modules.
-pub type Executive = executive::Executive<Runtime, Block, Context, Balances, AllModules>;
+/// Transaction fees are charged by the multisig module, which may charge them to a wallet.
+pub type Executive = executive::Executive<Runtime, Block, Context, Multisig, AllModules>;
 
 #[cfg(feature = "std")]
 use opaque::Block as GBlock;
@@ -1,4 +1,5 @@
 use rstd::prelude::*;
+use rstd::result;
 
 // Encoding library
 use parity_codec::{Encode, Decode, HasCompact};
@@ -9,7 +10,10 @@ use srml_support::{StorageMap, StorageValue, dispatch::Result};
 //use srml_support::{StorageValue, StorageMap, Parameter, Dispatchable, IsSubType};
 
 // Enables us to do hashing
-use runtime_primitives::traits::Hash;
+use runtime_primitives::traits::{Hash, Zero, One, CheckedAdd, MakePayment, As};
+
+// Share of owners required to sign
+use runtime_primitives::Permill;
 
 // Enables access to account balances
 use {balances, system::{self, ensure_signed}};
@@ -23,39 +27,307 @@ pub trait Trait: balances::Trait + system::Trait {
 
 use balances::Address as Address;
 
+/// Maximal number of rules in a wallet spending policy.
+const MAX_POLICY_RULES: usize = 16;
+
+/// Maximal number of conditions in a spending policy rule.
+const MAX_RULE_CONDITIONS: usize = 8;
+
+/// Maximal length of a withdrawal memo in bytes.
+const MAX_MEMO_LENGTH: usize = 64;
+
+/// Maximal length of an approval comment in bytes.
+const MAX_COMMENT_LENGTH: usize = 128;
+
+/// Amount an owner may spend from a wallet on their own within each period.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Allowance<Balance, BlockNumber> {
+    /// Amount which may be spent per period.
+    pub limit: Balance,
+    /// Length of a period in blocks.
+    pub period: BlockNumber,
+    /// Block at which the current period has started.
+    pub period_start: BlockNumber,
+    /// Amount already spent in the current period.
+    pub spent: Balance,
+}
+
+/// Role of a wallet owner.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Role {
+    /// Creates and approves operations.
+    Approver,
+    /// Creates operations, but its approval doesn't count towards the quorum.
+    Proposer,
+    /// Can't sign operations, only follows the wallet events.
+    Observer,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Approver
+    }
+}
+
+/// Step of a multi-stage approval of withdrawals.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Stage<AccountId> {
+    /// Owners who approve withdrawals at this stage.
+    pub owners: Vec<AccountId>,
+    /// Number of their signatures required to pass the stage.
+    pub signatures_required: u64,
+}
+
+/// Kind of a wallet operation, as seen by spending policies.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum CallKind {
+    /// Withdrawal of funds.
+    Withdraw,
+    /// Change of the wallet settings.
+    Management,
+}
+
+/// Condition of a spending policy rule.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Condition<AccountId, Balance, BlockNumber> {
+    /// Operation is of the kind.
+    Call(CallKind),
+    /// Withdrawn value is at least the amount.
+    MinValue(Balance),
+    /// Withdrawn value is at most the amount.
+    MaxValue(Balance),
+    /// Withdrawal destination is one of the accounts.
+    DestinationIn(Vec<AccountId>),
+    /// Operation is signed within the blocks [from, until).
+    Window(BlockNumber, BlockNumber),
+}
+
+/// Rule of a wallet spending policy: operations meeting all the conditions require
+/// the signatures of all the approvers, at least `signatures_required` signatures in
+/// total and, for withdrawals, wait for the delay.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Rule<AccountId, Balance, BlockNumber> {
+    /// Conditions the operation has to meet for the rule to apply.
+    pub conditions: Vec<Condition<AccountId, Balance, BlockNumber>>,
+    /// Owners who have to sign the operation.
+    pub approvers: Vec<AccountId>,
+    /// Number of signatures required.
+    pub signatures_required: u64,
+    /// Number of blocks a withdrawal waits after gathering the signatures.
+    pub delay: BlockNumber,
+}
+
+/// Operation gathering signatures, withdrawal or wallet management.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Proposal<AccountId, Balance> {
+    /// Wallet of the operation.
+    pub wallet: AccountId,
+    /// Owner who has proposed the operation.
+    pub proposer: AccountId,
+    /// Deposit reserved from the proposer until the operation is approved or cancelled.
+    pub deposit: Balance,
+}
+
+/// Amount of a wallet locked for a counterparty against a hash until a timeout.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct HashLock<AccountId, Balance, BlockNumber, Hash> {
+    /// Wallet the amount is reserved on.
+    pub wallet: AccountId,
+    /// Counterparty who may claim the amount.
+    pub to: AccountId,
+    /// Locked amount.
+    pub value: Balance,
+    /// Hash of the preimage revealed by the counterparty to claim the amount.
+    pub hashlock: Hash,
+    /// Last block at which the amount may be claimed.
+    pub timeout: BlockNumber,
+}
+
+/// Conditions on the block and wallet balance at which a withdrawal may execute.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ExecutionWindow<BlockNumber, Balance> {
+    /// Block from which the withdrawal may execute.
+    pub earliest: BlockNumber,
+    /// Block until which the withdrawal may execute, zero if unlimited.
+    pub latest: BlockNumber,
+    /// Balance the vault has to keep after the withdrawal.
+    pub min_balance: Balance,
+}
+
+/// Withdrawal gathering signatures.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PendingWithdrawal<AccountId, Balance, BlockNumber, Hash> {
+    /// Wallet the funds are withdrawn from.
+    pub wallet: AccountId,
+    /// Sub-vault of the wallet the funds are withdrawn from.
+    pub vault: u32,
+    /// Destination of the withdrawal.
+    pub to: AccountId,
+    /// Amount of the withdrawal.
+    pub value: Balance,
+    /// Reference of the withdrawal, such as an invoice number.
+    pub memo: Vec<u8>,
+    /// Operation of the same wallet which has to execute before the withdrawal.
+    pub after: Option<Hash>,
+    /// Conditions under which the withdrawal may execute.
+    pub window: ExecutionWindow<BlockNumber, Balance>,
+    /// Hashlock and timeout of the lock the amount is placed in instead of being paid, if any.
+    pub lock: Option<(Hash, BlockNumber)>,
+    /// Owner who has proposed the withdrawal.
+    pub proposer: AccountId,
+    /// Whether the amount is reserved on the vault account.
+    pub reserved: bool,
+    /// Block at which the withdrawal expires, zero if it never expires.
+    pub expires_at: BlockNumber,
+    /// Number of times the withdrawal was amended.
+    pub revision: u32,
+}
+
+/// Withdrawal which has gathered the quorum and waits for the wallet delay to pass.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct QueuedWithdrawal<AccountId, Balance, BlockNumber, Hash> {
+    /// Wallet the funds are withdrawn from.
+    pub wallet: AccountId,
+    /// Sub-vault of the wallet the funds are withdrawn from.
+    pub vault: u32,
+    /// Destination of the withdrawal.
+    pub to: AccountId,
+    /// Amount of the withdrawal.
+    pub value: Balance,
+    /// Reference of the withdrawal, such as an invoice number.
+    pub memo: Vec<u8>,
+    /// Operation of the same wallet which has to execute before the withdrawal.
+    pub after: Option<Hash>,
+    /// Conditions under which the withdrawal may execute.
+    pub window: ExecutionWindow<BlockNumber, Balance>,
+    /// Hashlock and timeout of the lock the amount is placed in instead of being paid, if any.
+    pub lock: Option<(Hash, BlockNumber)>,
+    /// Owner who has proposed the withdrawal.
+    pub proposer: AccountId,
+    /// Whether the amount is reserved on the vault account.
+    pub reserved: bool,
+    /// Owners who have approved the withdrawal.
+    pub approvers: Vec<AccountId>,
+    /// Block at which the withdrawal is executed.
+    pub execute_at: BlockNumber,
+}
+
+/// Migration of a wallet which has gathered the quorum and waits for the wallet delay to pass.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct QueuedMigration<AccountId, BlockNumber, Hash> {
+    /// Operation approving the migration.
+    pub operation: Hash,
+    /// Owners of the successor.
+    pub owners: Vec<AccountId>,
+    /// Signatures quorum of the successor.
+    pub signatures_required: u64,
+    /// Owners who have to sign every withdrawal of the successor.
+    pub mandatory: Vec<AccountId>,
+    /// Block at which the migration is executed.
+    pub execute_at: BlockNumber,
+}
+
 // TODO special type for multisig id
 decl_module! {
   pub struct Module<T: Trait> for enum Call where origin: T::Origin {
     fn deposit_event() = default;
 
     // creates new multi-signature wallet
-    fn create(origin, owners: Vec<Address<T>>, signatures_required: <u64 as HasCompact>::Type) -> Result {
+    // mandatory owners have to sign every withdrawal in addition to the quorum
+    fn create(origin, owners: Vec<Address<T>>, signatures_required: <u64 as HasCompact>::Type, mandatory: Vec<Address<T>>) -> Result {
         let sender = ensure_signed(origin)?;
 
-        let owners = owners.iter().map(|owner| <balances::Module<T>>::lookup(owner.clone()).unwrap()).collect::<Vec<_>>();
+        Self::create_wallet(&sender, owners, signatures_required.into(), mandatory)?;
+
+        Ok(())
+    }
+
+    // creates new multi-signature wallet which becomes active once all the owners accept
+    // their invitations, the creator accepts it by creating
+    // the deposit is reserved from the creator until the wallet is activated or cancelled
+    fn create_with_invitations(
+        origin,
+        owners: Vec<Address<T>>,
+        signatures_required: <u64 as HasCompact>::Type,
+        mandatory: Vec<Address<T>>,
+        deposit: <T::Balance as HasCompact>::Type
+    ) -> Result {
+        let sender = ensure_signed(origin)?;
         let signatures_required: u64 = signatures_required.into();
+        let deposit: T::Balance = deposit.into();
 
-        if 0 == owners.len() || owners.len() > 64 {
-            return Err("invalid number of owners");
+        let (owners, mandatory) = Self::check_owners(owners, signatures_required, mandatory)?;
+        let invitations = owners.iter().filter(|owner| **owner != sender).cloned().collect::<Vec<_>>();
+        if !invitations.is_empty() && !deposit.is_zero() {
+            Self::reserve_funds(&sender, deposit)?;
         }
-        if 0 == signatures_required || signatures_required > owners.len() as u64 {
-            return Err("invalid number of signatures");
+
+        let wallet_id = Self::register_wallet(&sender, owners, signatures_required, mandatory);
+        if !invitations.is_empty() {
+            for owner in invitations.iter() {
+                Self::deposit_event(RawEvent::Invited(wallet_id.clone(), owner.clone()));
+            }
+            <Invitations<T>>::insert(&wallet_id, invitations);
+            <Creator<T>>::insert(&wallet_id, sender);
+            if !deposit.is_zero() {
+                <CreationDeposit<T>>::insert(&wallet_id, deposit);
+            }
         }
 
-        let this_nonce: u64 = Self::global_nonce();
-        <GlobalNonce<T>>::mutate(|nonce| *nonce += 1);
+        Ok(())
+    }
 
-        let mut buf = Vec::new();
-        buf.extend_from_slice(&sender.encode());
-        buf.extend_from_slice(&this_nonce.encode());
-        let h: T::Hash = T::Hashing::hash(&buf[..]);
+    // accepts the invitation to own the wallet
+    fn accept_invitation(origin, wallet: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
 
-        let wallet_id = T::AccountId::decode(&mut &h.encode()[..]).unwrap();
+        let mut invitations = Self::invitations(&wallet);
+        ensure!(invitations.contains(&who), "sender isn't invited");
+        invitations.retain(|invited| *invited != who);
 
-        <Owners<T>>::insert(&wallet_id, owners);
-        <Signatures<T>>::insert(&wallet_id, signatures_required);
+        Self::deposit_event(RawEvent::InvitationAccepted(wallet.clone(), who));
+
+        if invitations.is_empty() {
+            <Invitations<T>>::remove(&wallet);
+            Self::release_creation_deposit(&wallet);
+            Self::deposit_event(RawEvent::Activated(wallet));
+        } else {
+            <Invitations<T>>::insert(&wallet, invitations);
+        }
+
+        Ok(())
+    }
+
+    // cancels the wallet which hasn't become active, the creator recovers the creation deposit
+    // funds sent to the wallet by anyone are left on it
+    fn cancel_wallet(origin, wallet: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
 
-        Self::deposit_event(RawEvent::Created(wallet_id));
+        ensure!(!Self::invitations(&wallet).is_empty(), "wallet isn't pending activation");
+        ensure!(Self::creator(&wallet) == Some(who.clone()), "sender isn't creator");
+
+        Self::release_creation_deposit(&wallet);
+
+        <Owners<T>>::remove(&wallet);
+        <Signatures<T>>::remove(&wallet);
+        <Mandatory<T>>::remove(&wallet);
+        <Invitations<T>>::remove(&wallet);
+
+        Self::deposit_event(RawEvent::WalletCancelled(wallet));
 
         Ok(())
     }
@@ -66,250 +338,4160 @@ decl_module! {
         let who = ensure_signed(origin)?;
         let wallet = <balances::Module<T>>::lookup(wallet)?;
         let to = <balances::Module<T>>::lookup(to)?;
-        let value = value.into();
 
-        ensure!(<Owners<T>>::exists(wallet.clone()), "wallet doesn't exists");
+        Self::request_withdrawal(who, wallet, 0, to, value.into(), Vec::new(), None, Default::default(), None)
+    }
 
-		let owners = <Owners<T>>::get(wallet.clone());
-        ensure!(owners.iter().any(|owner| *owner == who), "sender isn't owner");
+    // requests withdrawal from a sub-vault of a wallet, vault 0 is the wallet itself
+    fn withdraw_from_vault(
+        origin,
+        wallet: Address<T>,
+        vault: <u32 as HasCompact>::Type,
+        to: Address<T>,
+        value: <T::Balance as HasCompact>::Type
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
 
-    	let index = owners.iter().position(|owner| *owner == who).unwrap();
+        Self::request_withdrawal(who, wallet, vault.into(), to, value.into(), Vec::new(), None, Default::default(), None)
+    }
 
-		let mut buf = Vec::new();
-		buf.append(&mut wallet.encode());
-		buf.append(&mut to.encode());
-		buf.append(&mut value.encode());
+    // requests withdrawal from a sub-vault of a wallet with a memo referencing the payment
+    // the memo is part of the operation, owners sign the withdrawal with the same memo
+    fn withdraw_with_memo(
+        origin,
+        wallet: Address<T>,
+        vault: <u32 as HasCompact>::Type,
+        to: Address<T>,
+        value: <T::Balance as HasCompact>::Type,
+        memo: Vec<u8>
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        ensure!(memo.len() <= MAX_MEMO_LENGTH, "memo is too long");
 
-        let operation_hash = T::Hashing::hash(&buf[..]);
+        Self::request_withdrawal(who, wallet, vault.into(), to, value.into(), memo, None, Default::default(), None)
+    }
 
-        let mut bitmask: u64;
+    // requests withdrawal from a sub-vault of a wallet which executes only after the prerequisite
+    // operation of the same wallet has executed, and is cancelled if the prerequisite is cancelled
+    fn withdraw_after(
+        origin,
+        wallet: Address<T>,
+        vault: <u32 as HasCompact>::Type,
+        to: Address<T>,
+        value: <T::Balance as HasCompact>::Type,
+        memo: Vec<u8>,
+        prerequisite: T::Hash
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        ensure!(memo.len() <= MAX_MEMO_LENGTH, "memo is too long");
 
-		if !<OperationBitmask<T>>::exists(operation_hash) {
-			<OperationBitmask<T>>::insert(operation_hash, 1 << index);
-			bitmask = 1 << index;
-		}
-		else {
-			bitmask = <OperationBitmask<T>>::get(operation_hash);
-			ensure!((bitmask & (1 << index)) == 0, "sender already signed");
-			<OperationBitmask<T>>::mutate(operation_hash, |bitmask| *bitmask |= 1 << index);
-			bitmask |= 1 << index;
-		}
+        Self::request_withdrawal(who, wallet, vault.into(), to, value.into(), memo, Some(prerequisite), Default::default(), None)
+    }
 
-		if Self::signs_count(&bitmask) == <Signatures<T>>::get(&wallet) {
-			<OperationBitmask<T>>::remove(operation_hash);
-			Self::deposit_event(RawEvent::Withdraw(wallet.clone(), to.clone(), value));
-			return <balances::Module<T>>::transfer_without_sign(wallet, to.into(), value);
-		}
+    // requests withdrawal from a sub-vault of a wallet which executes only within the window
+    // withdrawals approved before the window opens execute in the block hook once it does
+    fn withdraw_conditional(
+        origin,
+        wallet: Address<T>,
+        vault: <u32 as HasCompact>::Type,
+        to: Address<T>,
+        value: <T::Balance as HasCompact>::Type,
+        memo: Vec<u8>,
+        prerequisite: Option<T::Hash>,
+        window: ExecutionWindow<T::BlockNumber, T::Balance>
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        ensure!(memo.len() <= MAX_MEMO_LENGTH, "memo is too long");
 
-        Ok(())
+        Self::request_withdrawal(who, wallet, vault.into(), to, value.into(), memo, prerequisite, window, None)
     }
 
-    // deposits are made using balances.transfer(to_wallet: T::AccountId, value: T::Balance)
-  }
-}
+    // changes the destination and amount of a withdrawal gathering signatures, any owner may do it
+    // approvals of the other owners are cleared, the amender approves the amended withdrawal
+    fn amend(origin, operation: T::Hash, to: Address<T>, value: <T::Balance as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        let value: T::Balance = value.into();
 
+        let pending = Self::pending(operation).ok_or("operation isn't pending")?;
+        let wallet = pending.wallet.clone();
+        Self::owner_index(&wallet, &who)?;
+        ensure!(Self::role((wallet.clone(), who.clone())) != Role::Observer, "sender is observer");
+        ensure!(Self::invitations(&wallet).is_empty(), "wallet isn't active");
+        ensure!(!Self::frozen(&wallet), "wallet is frozen");
+        ensure!(Self::allowlist(&wallet).contains(&to) || !Self::allowlist_only(&wallet), "destination isn't allowlisted");
 
-decl_storage! {
-	trait Store for Module<T: Trait> as Multisig {
-	    // Total number of multisig wallets
-	    pub GlobalNonce get(global_nonce): u64;
+        let amended = Self::pending_hash(&wallet, &PendingWithdrawal { to: to.clone(), value, ..pending.clone() });
+        ensure!(amended != operation, "operation isn't changed");
+        // dependents were approved against the original operation
+        ensure!(Self::dependents_of(operation).is_empty(), "operation has dependents");
+        ensure!(!<Pending<T>>::exists(amended) && !<Queue<T>>::exists(amended), "operation already exists");
+        // nor approved by hash
+        let amended_key = (wallet.clone(), amended);
+        ensure!(
+            !<Proposals<T>>::exists(&amended_key) && !<OperationBitmask<T>>::exists(&amended_key),
+            "operation already exists"
+        );
+        // the amender approves the amended withdrawal from its first stage
+        if Self::matching_rule(&wallet, CallKind::Withdraw, Some(&to), Some(value)).is_none() {
+            if let Some(stage) = Self::stages(&wallet).first() {
+                ensure!(stage.owners.contains(&who), "sender isn't in the current stage");
+            }
+        }
 
-	    // List of owners for each multisig
-		pub Owners get(owners): map T::AccountId => Vec<T::AccountId>;
+        if pending.reserved {
+            // reserved again as a whole, so the reservation of the withdrawal reaches the existential deposit
+            let vault = Self::vault_account(&wallet, pending.vault);
+            <balances::Module<T>>::unreserve(&vault, pending.value);
+            if let Err(err) = Self::reserve_funds(&vault, value) {
+                // can't fail, the amount has just been unreserved
+                let _ = <balances::Module<T>>::reserve(&vault, pending.value);
+                return Err(err);
+            }
+        }
 
-		// Signatures quorum for each multisig
-		pub Signatures get(signatures_required): map T::AccountId => u64;
+        <Pending<T>>::remove(operation);
+        Self::clear_votes(&wallet, operation);
+        Self::rekey_proposal(&wallet, operation, amended);
+        <Operations<T>>::mutate(&wallet, |operations| {
+            for existing in operations.iter_mut().filter(|existing| **existing == operation) {
+                *existing = amended;
+            }
+        });
+        if !pending.expires_at.is_zero() {
+            <Expiring<T>>::mutate(pending.expires_at, |operations| operations.push(amended));
+        }
 
-		// Bitmask of signatures for operations
-		// Operation is Hash(operation_name, wallet id, operation parameters)
-		pub OperationBitmask get(operation_bitmask): map T::Hash => u64;
-	}
-	add_extra_genesis {
-		config(_marker): ::std::marker::PhantomData<T>;
-		build(|_, _, _| {});
-	}
-}
+        let revision = pending.revision + 1;
+        <Pending<T>>::insert(amended, PendingWithdrawal { to: to.clone(), value, revision, ..pending.clone() });
+        Self::rename_dependency(operation, amended);
+        Self::sign(&wallet, &who, amended, false)?;
 
+        Self::deposit_event(RawEvent::Amended(wallet, operation, amended, revision, pending.to, pending.value, to, value));
 
-decl_event!(
-	pub enum Event<T> where
-	    AccountId = <T as system::Trait>::AccountId,
-		Balance = <T as balances::Trait>::Balance
-	{
-		/// Created new wallet identified by Runtime::AccountId type.
-		Created(AccountId),
-		Withdraw(AccountId, AccountId, Balance),
-	}
-);
+        Ok(())
+    }
 
+    // opens the next sub-vault of the wallet, requires quorum
+    // vaults share the owners and settings of the wallet
+    fn open_vault(origin, wallet: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
 
-impl<T: Trait> Module<T> {
-    // PUBLIC IMMUTABLES
+        let vault = Self::vault_count(&wallet) + 1;
 
-    /// The combined balance of `who`.
-    pub fn signs_count(bitmask: &u64) -> u64 {
-        let mut count = 0;
-        let mut mask = *bitmask;
-        while mask > 0 {
-            if mask & 1 == 1 {
-                count += 1;
-            }
-            mask >>= 1;
+        let operation_hash = Self::operation_hash(b"open_vault", &wallet, vault);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <VaultCount<T>>::insert(&wallet, vault);
+            Self::deposit_event(RawEvent::VaultOpened(wallet.clone(), vault, Self::vault_account(&wallet, vault)));
         }
 
-        return count;
+        Ok(())
     }
-}
 
+    // adds a destination to the wallet allowlist, requires quorum
+    fn add_allowed_destination(origin, wallet: Address<T>, destination: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let destination = <balances::Module<T>>::lookup(destination)?;
 
-#[cfg(test)]
-mod tests {
-    extern crate sr_io as runtime_io;
+        let mut allowlist = Self::allowlist(&wallet);
+        ensure!(!allowlist.contains(&destination), "destination is already allowlisted");
 
-    use super::*;
+        let operation_hash = Self::operation_hash(b"add_allowed_destination", &wallet, destination.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            allowlist.push(destination.clone());
+            <Allowlist<T>>::insert(&wallet, allowlist);
+            Self::deposit_event(RawEvent::AllowlistAdded(wallet, destination));
+        }
 
-    use keyring::Keyring;
-    use primitives::{H256, Blake2Hasher};
-    use runtime_primitives::BuildStorage;
-    use runtime_primitives::traits::{BlakeTwo256};
-    use runtime_primitives::testing::{Digest, DigestItem, Header};
-    use runtime_io::{with_externalities, TestExternalities};
-    use balances::address;
+        Ok(())
+    }
 
-    impl_outer_origin! {
-		pub enum Origin for Test {}
-	}
+    // removes a destination from the wallet allowlist, requires quorum
+    fn remove_allowed_destination(origin, wallet: Address<T>, destination: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let destination = <balances::Module<T>>::lookup(destination)?;
 
-    #[derive(Clone, Eq, PartialEq)]
-    pub struct Test;
+        let mut allowlist = Self::allowlist(&wallet);
+        ensure!(allowlist.contains(&destination), "destination isn't allowlisted");
 
-    impl system::Trait for Test {
-        type Origin = Origin;
-        type Index = u64;
-        type BlockNumber = u64;
-        type Hash = H256;
-        type Hashing = BlakeTwo256;
-        type Digest = Digest;
-        type AccountId = H256;
-        type Header = Header;
-        type Event = ();
-        type Log = DigestItem;
-    }
+        let operation_hash = Self::operation_hash(b"remove_allowed_destination", &wallet, destination.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            allowlist.retain(|allowed| *allowed != destination);
+            <Allowlist<T>>::insert(&wallet, allowlist);
+            Self::deposit_event(RawEvent::AllowlistRemoved(wallet, destination));
+        }
 
-    impl balances::Trait for Test {
-        type Balance = u64;
-        type AccountIndex = u64;
-        type OnFreeBalanceZero = ();
-        type EnsureAccountLiquid = ();
-        type Event = ();
+        Ok(())
     }
 
-    impl super::Trait for Test {
-        type Event = ();
-    }
+    // restricts withdrawals to allowlisted destinations, requires quorum
+    fn set_allowlist_only(origin, wallet: Address<T>, enabled: bool) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
 
-    type Balances = balances::Module<Test>;
-    type Multisig = Module<Test>;
-    type Address = balances::Address<Test>;
+        let operation_hash = Self::operation_hash(b"set_allowlist_only", &wallet, enabled);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <AllowlistOnly<T>>::insert(&wallet, enabled);
+            Self::deposit_event(RawEvent::AllowlistOnlySet(wallet, enabled));
+        }
 
-    fn new_test_ext() -> TestExternalities<Blake2Hasher> {
-        let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
-        t.extend(balances::GenesisConfig::<Test> {
-            _genesis_phantom_data: ::std::marker::PhantomData,
-            balances: vec![(Keyring::Alice.to_raw_public().into(), 100),
-                           (Keyring::Bob.to_raw_public().into(), 99),
-                           (Keyring::Charlie.to_raw_public().into(), 10)],
-            transaction_base_fee: 0,
-            transaction_byte_fee: 0,
-            transfer_fee: 0,
-            creation_fee: 0,
-            existential_deposit: 0,
-            reclaim_rebate: 0,
-        }.build_storage().unwrap().0);
-        t.into()
+        Ok(())
     }
 
-    fn address_of(user: Keyring) -> Address {
-        <Address as From<H256>>::from(user.to_raw_public().into())
-    }
+    // sets a lower quorum for withdrawals to allowlisted destinations, requires quorum
+    // zero disables the lower quorum
+    fn set_allowlist_signatures(origin, wallet: Address<T>, signatures_required: <u64 as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let signatures_required: u64 = signatures_required.into();
 
-    fn signature_of(user: Keyring) -> <Test as system::Trait>::Origin {
-        Some(user.to_raw_public().into()).into()
-    }
+        ensure!(signatures_required <= Self::signatures_required(&wallet), "invalid number of signatures");
 
-    fn account_id_of(user: Address) -> <Test as system::Trait>::AccountId {
-        match user {
-            address::Address::Id(i) => i,
-            address::Address::Index(_) => panic!("invalid account id"),
+        let operation_hash = Self::operation_hash(b"set_allowlist_signatures", &wallet, signatures_required);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <AllowlistSignatures<T>>::insert(&wallet, signatures_required);
+            Self::deposit_event(RawEvent::AllowlistSignaturesSet(wallet, signatures_required));
         }
+
+        Ok(())
     }
 
-    fn wallet_id_of(creator: Keyring, nonce: u64) -> Address {
-        let creator_addr = account_id_of(address_of(creator));
+    // grants an owner an allowance which the owner may spend from the wallet alone, requires quorum
+    // zero limit revokes the allowance
+    fn set_allowance(origin, wallet: Address<T>, owner: Address<T>, limit: <T::Balance as HasCompact>::Type, period: T::BlockNumber) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let owner = <balances::Module<T>>::lookup(owner)?;
+        let limit: T::Balance = limit.into();
 
-        let mut buf = Vec::new();
-        buf.extend_from_slice(&creator_addr.encode());
-        buf.extend_from_slice(&nonce.encode());
-        let h: <Test as system::Trait>::Hash = <Test as system::Trait>::Hashing::hash(&buf[..]);
+        ensure!(Self::owners(&wallet).contains(&owner), "allowance holder isn't owner");
+        ensure!(limit.is_zero() || Self::role((wallet.clone(), owner.clone())) != Role::Observer, "allowance holder is observer");
+        ensure!(limit.is_zero() || !period.is_zero(), "invalid allowance period");
 
-        return <Test as system::Trait>::AccountId::decode(&mut &h.encode()[..]).unwrap().into();
-    }
+        let operation_hash = Self::operation_hash(b"set_allowance", &wallet, (owner.clone(), limit, period));
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            let key = (wallet.clone(), owner.clone());
+            if limit.is_zero() {
+                <Allowances<T>>::remove(&key);
+            } else {
+                <Allowances<T>>::insert(&key, Allowance {
+                    limit,
+                    period,
+                    period_start: <system::Module<T>>::block_number(),
+                    spent: Zero::zero(),
+                });
+            }
+            Self::deposit_event(RawEvent::AllowanceSet(wallet, owner, limit, period));
+        }
 
-    #[test]
-    fn genesis_nonce() {
-        with_externalities(&mut new_test_ext(), || {
-            assert_eq!(Multisig::global_nonce(), 0);
-        });
+        Ok(())
     }
 
-    #[test]
-    fn create() {
-        with_externalities(&mut new_test_ext(), || {
-            assert_ok!(Multisig::create(signature_of(Keyring::Alice),
-                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)],
-                2.into()));
+    // spends the sender's allowance from the wallet without other owners' signatures
+    fn spend_allowance(origin, wallet: Address<T>, to: Address<T>, value: <T::Balance as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        let value: T::Balance = value.into();
 
-            assert_eq!(Multisig::signatures_required(account_id_of(wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1))), 2);
-        });
-    }
+        Self::owner_index(&wallet, &who)?;
+        ensure!(!Self::frozen(&wallet), "wallet is frozen");
+        ensure!(!Self::allowlist_only(&wallet) || Self::allowlist(&wallet).contains(&to), "destination isn't allowlisted");
 
-    #[test]
-    fn withdraw() {
-        with_externalities(&mut new_test_ext(), || {
-            assert_ok!(Multisig::create(signature_of(Keyring::Alice),
-                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)],
-                2.into()
-            ));
+        let key = (wallet.clone(), who.clone());
+        ensure!(Self::role(&key) != Role::Observer, "sender is observer");
+        ensure!(!Self::has_veto_power(&key), "veto holder can't initiate payments");
+        ensure!(<Allowances<T>>::exists(&key), "sender has no allowance");
 
-            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+        let mut allowance = Self::allowance(&key);
+        let now = <system::Module<T>>::block_number();
+        // a period ending beyond the last block never elapses
+        if allowance.period_start.checked_add(&allowance.period).map_or(false, |period_end| now >= period_end) {
+            allowance.period_start = now;
+            allowance.spent = Zero::zero();
+        }
 
-            assert_ok!(Balances::transfer(signature_of(Keyring::Alice),
+        let spent = allowance.spent.checked_add(&value).ok_or("allowance exceeded")?;
+        ensure!(spent <= allowance.limit, "allowance exceeded");
+
+        <balances::Module<T>>::transfer_without_sign(wallet.clone(), to.clone().into(), value)?;
+
+        allowance.spent = spent;
+        <Allowances<T>>::insert(&key, allowance);
+
+        Self::deposit_event(RawEvent::AllowanceSpent(wallet, who, to, value));
+
+        Ok(())
+    }
+
+    // adds an owner to the wallet, requires quorum
+    // pending approvals of the wallet are reset
+    fn add_owner(origin, wallet: Address<T>, owner: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let owner = <balances::Module<T>>::lookup(owner)?;
+
+        let mut owners = Self::owners(&wallet);
+        ensure!(!owners.contains(&owner), "owner already exists");
+        ensure!(owners.len() < 64, "invalid number of owners");
+
+        let approvers = Self::signs_count(&Self::approvers_mask(&wallet));
+        Self::ensure_quorum_reachable(&wallet, owners.len() as u64 + 1, approvers + 1)?;
+
+        let operation_hash = Self::operation_hash(b"add_owner", &wallet, owner.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            owners.push(owner.clone());
+            owners.sort();
+            Self::change_owners(&wallet, owners);
+            Self::deposit_event(RawEvent::OwnerAdded(wallet, owner));
+        }
+
+        Ok(())
+    }
+
+    // removes an owner from the wallet, requires quorum
+    // pending approvals of the wallet are reset
+    // allowed while the wallet is frozen, so the owners can remove an owner who keeps freezing it
+    fn remove_owner(origin, wallet: Address<T>, owner: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let owner = <balances::Module<T>>::lookup(owner)?;
+
+        let mut owners = Self::owners(&wallet);
+        ensure!(owners.contains(&owner), "owner doesn't exist");
+        ensure!(owners.len() > 1, "invalid number of owners");
+        ensure!(!Self::mandatory(&wallet).contains(&owner), "can't remove mandatory signer");
+        ensure!(!Self::is_stage_member(&wallet, &owner), "can't remove stage member");
+        ensure!(!Self::is_rule_approver(&wallet, &owner), "can't remove rule approver");
+
+        let key = (wallet.clone(), owner.clone());
+        let mut approvers = Self::signs_count(&Self::approvers_mask(&wallet));
+        if Self::role(&key) == Role::Approver {
+            approvers -= 1;
+        }
+        Self::ensure_quorum_reachable(&wallet, owners.len() as u64 - 1, approvers)?;
+
+        let operation_hash = Self::operation_hash(b"remove_owner", &wallet, owner.clone());
+        if Self::approve_frozen_management(&wallet, &who, operation_hash)? {
+            owners.retain(|existing| *existing != owner);
+            <Roles<T>>::remove(&key);
+            <VetoPower<T>>::remove(&key);
+            <Allowances<T>>::remove(&key);
+            Self::change_owners(&wallet, owners);
+            Self::deposit_event(RawEvent::OwnerRemoved(wallet, owner));
+        }
+
+        Ok(())
+    }
+
+    // sets the quorum as an absolute number of signatures, requires quorum
+    fn set_signatures_required(origin, wallet: Address<T>, signatures_required: <u64 as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let signatures_required: u64 = signatures_required.into();
+
+        ensure!(0 < signatures_required && signatures_required <= Self::owners(&wallet).len() as u64, "invalid number of signatures");
+        ensure!(signatures_required <= Self::signs_count(&Self::approvers_mask(&wallet)), "not enough approvers left");
+        ensure!(Self::mandatory(&wallet).len() as u64 <= signatures_required, "too many mandatory signers");
+
+        let operation_hash = Self::operation_hash(b"set_signatures_required", &wallet, signatures_required);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <Threshold<T>>::remove(&wallet);
+            <Signatures<T>>::insert(&wallet, signatures_required);
+            Self::deposit_event(RawEvent::SignaturesSet(wallet, signatures_required));
+        }
+
+        Ok(())
+    }
+
+    // sets the quorum as a share of owners which follows owners changes, requires quorum
+    // see `relative_signatures_required` for the rounding rule
+    fn set_threshold(origin, wallet: Address<T>, threshold: Permill) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let owners = Self::owners(&wallet).len() as u64;
+        let signatures_required = Self::relative_signatures_required(threshold, owners);
+        ensure!(signatures_required <= Self::signs_count(&Self::approvers_mask(&wallet)), "not enough approvers left");
+        ensure!(Self::mandatory(&wallet).len() as u64 <= signatures_required, "too many mandatory signers");
+
+        let operation_hash = Self::operation_hash(b"set_threshold", &wallet, threshold);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <Threshold<T>>::insert(&wallet, threshold);
+            <Signatures<T>>::insert(&wallet, signatures_required);
+            Self::deposit_event(RawEvent::ThresholdSet(wallet.clone(), threshold));
+            Self::deposit_event(RawEvent::SignaturesSet(wallet, signatures_required));
+        }
+
+        Ok(())
+    }
+
+    // sets amount tiers of withdrawal quorum as (maximal value, signatures required) pairs, requires quorum
+    // withdrawals above the last tier require the wallet quorum, empty tiers remove them
+    fn set_tiers(origin, wallet: Address<T>, tiers: Vec<(T::Balance, u64)>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let signatures_required = Self::signatures_required(&wallet);
+        for (i, &(limit, signatures)) in tiers.iter().enumerate() {
+            ensure!(0 < signatures && signatures <= signatures_required, "invalid number of signatures");
+            if i > 0 {
+                let (previous_limit, previous_signatures) = tiers[i - 1];
+                ensure!(previous_limit < limit && previous_signatures <= signatures, "tiers must be ascending");
+            }
+        }
+
+        let operation_hash = Self::operation_hash(b"set_tiers", &wallet, tiers.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            if tiers.is_empty() {
+                <Tiers<T>>::remove(&wallet);
+            } else {
+                <Tiers<T>>::insert(&wallet, tiers);
+            }
+            Self::deposit_event(RawEvent::TiersSet(wallet));
+        }
+
+        Ok(())
+    }
+
+    // sets ordered approval stages for withdrawals, requires quorum
+    // withdrawals of a wallet with stages are approved by the stages only, without the quorum
+    // and amount tiers, mandatory signers have to be stage members and sign in their stages,
+    // empty stages remove them
+    fn set_stages(origin, wallet: Address<T>, stages: Vec<Stage<T::AccountId>>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let owners = Self::owners(&wallet);
+        let approvers_mask = Self::approvers_mask(&wallet);
+        for stage in stages.iter() {
+            for (i, member) in stage.owners.iter().enumerate() {
+                ensure!(owners.contains(member), "stage member isn't owner");
+                ensure!(!stage.owners[..i].contains(member), "duplicate stage member");
+            }
+            let approvers = Self::signs_count(&(Self::members_mask(&owners, &stage.owners) & approvers_mask));
+            ensure!(0 < stage.signatures_required && stage.signatures_required <= approvers, "invalid number of signatures");
+        }
+        for signer in Self::mandatory(&wallet).iter() {
+            ensure!(stages.is_empty() || stages.iter().any(|stage| stage.owners.contains(signer)), "mandatory signer isn't stage member");
+        }
+
+        let operation_hash = Self::operation_hash(b"set_stages", &wallet, stages.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            if stages.is_empty() {
+                <Stages<T>>::remove(&wallet);
+            } else {
+                <Stages<T>>::insert(&wallet, stages);
+            }
+            Self::deposit_event(RawEvent::StagesSet(wallet));
+        }
+
+        Ok(())
+    }
+
+    // sets the spending policy of the wallet, requires quorum
+    // the first rule whose conditions are met decides on a withdrawal instead of the quorum,
+    // amount tiers and stages, management operations need the rule in addition to the quorum,
+    // empty policy removes it
+    fn set_policy(origin, wallet: Address<T>, policy: Vec<Rule<T::AccountId, T::Balance, T::BlockNumber>>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        ensure!(policy.len() <= MAX_POLICY_RULES, "too many policy rules");
+
+        let owners = Self::owners(&wallet);
+        let approvers_mask = Self::approvers_mask(&wallet);
+        for rule in policy.iter() {
+            ensure!(rule.conditions.len() <= MAX_RULE_CONDITIONS, "too many rule conditions");
+            for condition in rule.conditions.iter() {
+                if let Condition::Window(from, until) = *condition {
+                    ensure!(from < until, "invalid rule window");
+                }
+            }
+            for (i, approver) in rule.approvers.iter().enumerate() {
+                ensure!(owners.contains(approver), "rule approver isn't owner");
+                ensure!(!rule.approvers[..i].contains(approver), "duplicate rule approver");
+            }
+            let required_mask = Self::members_mask(&owners, &rule.approvers);
+            ensure!(required_mask & approvers_mask == required_mask, "rule approver must be approver");
+            ensure!(rule.approvers.len() as u64 <= rule.signatures_required, "too many rule approvers");
+            ensure!(0 < rule.signatures_required && rule.signatures_required <= Self::signs_count(&approvers_mask), "invalid number of signatures");
+        }
+
+        let operation_hash = Self::operation_hash(b"set_policy", &wallet, policy.clone());
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            if policy.is_empty() {
+                <Policy<T>>::remove(&wallet);
+            } else {
+                <Policy<T>>::insert(&wallet, policy);
+            }
+            Self::deposit_event(RawEvent::PolicySet(wallet));
+        }
+
+        Ok(())
+    }
+
+    // locks an amount of the wallet for the counterparty, who may claim it with the preimage
+    // of the hashlock until the timeout, after which it returns to the wallet
+    // approved and queued like a withdrawal, the amount is locked instead of paid
+    // the amount can't be claimed while the wallet is frozen
+    fn lock(
+        origin,
+        wallet: Address<T>,
+        to: Address<T>,
+        value: <T::Balance as HasCompact>::Type,
+        hashlock: T::Hash,
+        timeout: T::BlockNumber
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let to = <balances::Module<T>>::lookup(to)?;
+        let value: T::Balance = value.into();
+
+        ensure!(timeout > <system::Module<T>>::block_number(), "timeout has passed");
+        // the amount returns to the wallet in the block after the timeout
+        timeout.checked_add(&One::one()).ok_or("invalid timeout")?;
+
+        let operation_hash = Self::lock_hash(&wallet, &to, value, hashlock, timeout);
+        ensure!(!<Locks<T>>::exists(operation_hash), "operation is already locked");
+        // checked before signing, so the final approval doesn't fail to reserve
+        if !Self::pending(operation_hash).map_or(false, |pending| pending.reserved) {
+            Self::ensure_can_reserve(&wallet, value)?;
+        }
+
+        Self::request_withdrawal(who, wallet, 0, to, value, Vec::new(), None, Default::default(), Some((hashlock, timeout)))
+    }
+
+    // pays a locked amount to its counterparty, anyone knowing the preimage may call it
+    fn claim(origin, lock: T::Hash, preimage: Vec<u8>) -> Result {
+        ensure_signed(origin)?;
+
+        let locked = Self::hash_lock(lock).ok_or("lock doesn't exist")?;
+        ensure!(<system::Module<T>>::block_number() <= locked.timeout, "lock has timed out");
+        ensure!(T::Hashing::hash(&preimage[..]) == locked.hashlock, "invalid preimage");
+        ensure!(!Self::frozen(&locked.wallet), "wallet is frozen");
+
+        Self::repatriate(&locked.wallet, &locked.to, locked.value)?;
+        <Locks<T>>::remove(lock);
+        Self::deposit_event(RawEvent::Claimed(locked.wallet, lock, preimage));
+
+        Ok(())
+    }
+
+    // approves an operation by its hash only, without revealing its parameters, with an optional comment
+    // the operation is executed once an owner calls it with the parameters matching the hash
+    fn approve(origin, wallet: Address<T>, operation: T::Hash, comment: Vec<u8>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        ensure!(comment.len() <= MAX_COMMENT_LENGTH, "comment is too long");
+
+        // approvals of a pending withdrawal are limited to its current stage, as when revealed
+        if let Some(pending) = Self::pending(operation).filter(|pending| pending.wallet == wallet) {
+            let stages = Self::stages(&wallet);
+            if !stages.is_empty() && Self::matching_rule(&wallet, CallKind::Withdraw, Some(&pending.to), Some(pending.value)).is_none() {
+                let stage = Self::current_stage(&wallet, operation, &stages);
+                ensure!(stage.owners.contains(&who), "sender isn't in the current stage");
+            }
+        }
+
+        Self::sign(&wallet, &who, operation, false)?;
+        Self::deposit_event(RawEvent::Approved(wallet.clone(), operation, who.clone()));
+        if !comment.is_empty() {
+            Self::deposit_event(RawEvent::Commented(wallet, operation, who, comment));
+        }
+
+        Ok(())
+    }
+
+    // rejects an operation with an optional comment, the operation is cancelled once
+    // the owners who haven't rejected it can't gather its quorum anymore
+    fn reject(origin, wallet: Address<T>, operation: T::Hash, comment: Vec<u8>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        ensure!(comment.len() <= MAX_COMMENT_LENGTH, "comment is too long");
+
+        let index = Self::owner_index(&wallet, &who)?;
+        ensure!(Self::role((wallet.clone(), who.clone())) == Role::Approver, "sender can't reject operations");
+
+        let key = (wallet.clone(), operation);
+        let pending = Self::pending(operation).filter(|pending| pending.wallet == wallet);
+        ensure!(
+            pending.is_some() || <Proposals<T>>::exists(&key) || <OperationBitmask<T>>::exists(&key),
+            "operation isn't pending"
+        );
+
+        let rejections = Self::rejections(&key);
+        ensure!((rejections & (1 << index)) == 0, "sender already rejected");
+        let rejections = rejections | (1 << index);
+        <Rejections<T>>::insert(&key, rejections);
+        Self::settle_fee(&wallet, &who);
+
+        // a rejection withdraws the approval of the sender
+        let bitmask = Self::operation_bitmask(&key);
+        if (bitmask & (1 << index)) != 0 {
+            <OperationBitmask<T>>::insert(&key, bitmask & !(1 << index));
+        }
+
+        Self::deposit_event(RawEvent::Rejected(wallet.clone(), operation, who.clone()));
+        if !comment.is_empty() {
+            Self::deposit_event(RawEvent::Commented(wallet.clone(), operation, who, comment));
+        }
+
+        // the approvers who haven't rejected the operation are checked against the
+        // requirement applied when it is approved
+        let remaining = Self::approvers_mask(&wallet) & !rejections;
+        let approvable = match pending {
+            Some(ref pending) => Self::withdrawal_approvable(&wallet, operation, &pending.to, pending.value, remaining),
+            None => Self::management_approved(&wallet, remaining),
+        };
+        if !approvable {
+            match pending {
+                Some(pending) => Self::cancel_pending(operation, pending),
+                None => {
+                    Self::clear_votes(&wallet, operation);
+                    Self::close_proposal(&wallet, operation);
+                },
+            }
+            Self::deposit_event(RawEvent::Defeated(wallet, operation));
+        }
+
+        Ok(())
+    }
+
+    // freezes the wallet immediately, any single owner may do it
+    fn freeze(origin, wallet: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        Self::owner_index(&wallet, &who)?;
+        ensure!(Self::role((wallet.clone(), who.clone())) != Role::Observer, "sender is observer");
+        ensure!(!Self::frozen(&wallet), "wallet is already frozen");
+
+        <Frozen<T>>::insert(&wallet, true);
+        Self::deposit_event(RawEvent::Frozen(wallet, who));
+
+        Ok(())
+    }
+
+    // unfreezes the wallet, requires quorum
+    fn unfreeze(origin, wallet: Address<T>) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        ensure!(Self::frozen(&wallet), "wallet isn't frozen");
+
+        let operation_hash = Self::operation_hash(b"unfreeze", &wallet, ());
+        let (bitmask, signed) = Self::sign_or_reveal(&wallet, &who, operation_hash, true)?;
+
+        if Self::approvals(&wallet, bitmask) >= Self::signatures_required(&wallet) {
+            Self::clear_votes(&wallet, operation_hash);
+            Self::close_proposal(&wallet, operation_hash);
+            Self::refund_approvers(&wallet, &Self::signers(&wallet, bitmask));
+            <Frozen<T>>::remove(&wallet);
+            Self::deposit_event(RawEvent::Unfrozen(wallet.clone()));
+
+            // withdrawals and the migration which came due while the wallet was frozen execute now
+            for operation in <Suspended<T>>::take(&wallet) {
+                if let Some(queued) = Self::queued(operation) {
+                    Self::execute_queued(operation, queued);
+                }
+            }
+            let now = <system::Module<T>>::block_number();
+            if let Some(migration) = Self::queued_migration(&wallet).filter(|migration| migration.execute_at <= now) {
+                <Migrations<T>>::remove(&wallet);
+                Self::execute_migration(&wallet, migration);
+            }
+        } else {
+            ensure!(!signed, "sender already signed");
+        }
+
+        Ok(())
+    }
+
+    // moves the wallet to a successor with new owners, quorum and mandatory signers, requires quorum
+    // balances of the wallet and its sub-vaults and settings which don't depend on the owners
+    // are moved, pending and queued withdrawals are carried over with approvals reset
+    // the migration waits for the wallet delay, during which a veto holder may cancel it
+    fn migrate(
+        origin,
+        wallet: Address<T>,
+        owners: Vec<Address<T>>,
+        signatures_required: <u64 as HasCompact>::Type,
+        mandatory: Vec<Address<T>>
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let signatures_required: u64 = signatures_required.into();
+
+        let (owners, mandatory) = Self::check_owners(owners, signatures_required, mandatory)?;
+        ensure!(Self::queued_migration(&wallet).is_none(), "migration is already queued");
+
+        let now = <system::Module<T>>::block_number();
+        let execute_at = now.checked_add(&Self::delay(&wallet)).ok_or("delay is too long")?;
+
+        let operation_hash = Self::operation_hash(b"migrate", &wallet, (owners.clone(), signatures_required, mandatory.clone()));
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            let migration = QueuedMigration { operation: operation_hash, owners, signatures_required, mandatory, execute_at };
+            if execute_at == now {
+                Self::execute_migration(&wallet, migration);
+            } else {
+                <Migrations<T>>::insert(&wallet, migration);
+                <MigrationsAt<T>>::mutate(execute_at, |wallets| wallets.push(wallet.clone()));
+                Self::deposit_event(RawEvent::MigrationQueued(wallet, operation_hash, execute_at));
+            }
+        }
+
+        Ok(())
+    }
+
+    // sets the number of blocks withdrawals wait after gathering the quorum, requires quorum
+    fn set_delay(origin, wallet: Address<T>, delay: T::BlockNumber) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let operation_hash = Self::operation_hash(b"set_delay", &wallet, delay);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <Delay<T>>::insert(&wallet, delay);
+            Self::deposit_event(RawEvent::DelaySet(wallet, delay));
+        }
+
+        Ok(())
+    }
+
+    // enables or disables reserving the amounts of new withdrawals on the wallet, requires quorum
+    // reserved withdrawals can't be overdrawn by other withdrawals gathering signatures
+    fn set_reserve_mode(origin, wallet: Address<T>, enabled: bool) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let operation_hash = Self::operation_hash(b"set_reserve_mode", &wallet, enabled);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <ReserveMode<T>>::insert(&wallet, enabled);
+            Self::deposit_event(RawEvent::ReserveModeSet(wallet, enabled));
+        }
+
+        Ok(())
+    }
+
+    // sets the number of blocks new withdrawals may gather signatures before expiring,
+    // zero disables expiry, requires quorum
+    fn set_lifetime(origin, wallet: Address<T>, lifetime: T::BlockNumber) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        let operation_hash = Self::operation_hash(b"set_lifetime", &wallet, lifetime);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <Lifetime<T>>::insert(&wallet, lifetime);
+            Self::deposit_event(RawEvent::LifetimeSet(wallet, lifetime));
+        }
+
+        Ok(())
+    }
+
+    // sets the amount the wallet may spend paying transaction fees of its owners, requires quorum
+    // the wallet pays the fees of the owners who have chosen it with `set_fee_payer`, a fee
+    // paid for a transaction which doesn't sign an operation of the wallet is charged back
+    // a budget can't be set while the wallet refunds fees on execution
+    fn set_fee_budget(origin, wallet: Address<T>, budget: <T::Balance as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let budget: T::Balance = budget.into();
+
+        ensure!(budget.is_zero() || Self::refund_limit(&wallet).is_zero(), "refund limit is set");
+
+        let operation_hash = Self::operation_hash(b"set_fee_budget", &wallet, budget);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <FeeBudget<T>>::insert(&wallet, budget);
+            Self::deposit_event(RawEvent::FeeBudgetSet(wallet, budget));
+        }
+
+        Ok(())
+    }
+
+    // chooses the wallet paying the transaction fees of the sender out of its fee budget,
+    // none makes the sender pay its fees again
+    fn set_fee_payer(origin, wallet: Option<Address<T>>) -> Result {
+        let who = ensure_signed(origin)?;
+
+        match wallet {
+            Some(wallet) => {
+                let wallet = <balances::Module<T>>::lookup(wallet)?;
+                Self::owner_index(&wallet, &who)?;
+                <FeePayer<T>>::insert(&who, wallet.clone());
+                Self::deposit_event(RawEvent::FeePayerSet(who, Some(wallet)));
+            },
+            None => {
+                <FeePayer<T>>::remove(&who);
+                Self::deposit_event(RawEvent::FeePayerSet(who, None));
+            },
+        }
+
+        Ok(())
+    }
+
+    // sets the maximum transaction fee refunded from the wallet to each approver of an
+    // executed operation, zero disables refunds, requires quorum
+    // refunds on execution can't be enabled while the wallet has a fee budget
+    fn set_refund_limit(origin, wallet: Address<T>, limit: <T::Balance as HasCompact>::Type) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let limit: T::Balance = limit.into();
+
+        ensure!(limit.is_zero() || Self::fee_budget(&wallet).is_zero(), "fee budget is set");
+
+        let operation_hash = Self::operation_hash(b"set_refund_limit", &wallet, limit);
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <RefundLimit<T>>::insert(&wallet, limit);
+            Self::deposit_event(RawEvent::RefundLimitSet(wallet, limit));
+        }
+
+        Ok(())
+    }
+
+    // sets the caps on operations gathering signatures in the wallet and per proposing owner,
+    // zero disables a cap, and the deposit reserved from the proposer of each operation, requires quorum
+    fn set_proposal_limits(
+        origin,
+        wallet: Address<T>,
+        max_proposals: <u32 as HasCompact>::Type,
+        max_owner_proposals: <u32 as HasCompact>::Type,
+        deposit: <T::Balance as HasCompact>::Type
+    ) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let max_proposals: u32 = max_proposals.into();
+        let max_owner_proposals: u32 = max_owner_proposals.into();
+        let deposit: T::Balance = deposit.into();
+
+        ensure!(deposit.is_zero() || deposit >= <balances::Module<T>>::existential_deposit(), "amount is below the existential deposit");
+
+        let operation_hash = Self::operation_hash(b"set_proposal_limits", &wallet, (max_proposals, max_owner_proposals, deposit));
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            <MaxProposals<T>>::insert(&wallet, max_proposals);
+            <MaxOwnerProposals<T>>::insert(&wallet, max_owner_proposals);
+            <ProposalDeposit<T>>::insert(&wallet, deposit);
+            Self::deposit_event(RawEvent::ProposalLimitsSet(wallet, max_proposals, max_owner_proposals, deposit));
+        }
+
+        Ok(())
+    }
+
+    // cancels an operation gathering signatures, can be called by its proposer only
+    fn cancel(origin, wallet: Address<T>, operation: T::Hash) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        Self::owner_index(&wallet, &who)?;
+        if let Some(pending) = Self::pending(operation).filter(|pending| pending.wallet == wallet) {
+            ensure!(pending.proposer == who, "sender isn't proposer");
+            Self::cancel_pending(operation, pending);
+        } else {
+            let proposal = Self::proposal((wallet.clone(), operation)).ok_or("operation isn't pending")?;
+            ensure!(proposal.proposer == who, "sender isn't proposer");
+
+            Self::clear_votes(&wallet, operation);
+            Self::close_proposal(&wallet, operation);
+        }
+        Self::deposit_event(RawEvent::Cancelled(wallet, operation));
+
+        Ok(())
+    }
+
+    // grants or revokes the power to veto queued withdrawals, requires quorum
+    fn set_veto_power(origin, wallet: Address<T>, owner: Address<T>, enabled: bool) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let owner = <balances::Module<T>>::lookup(owner)?;
+
+        ensure!(Self::owners(&wallet).contains(&owner), "veto holder isn't owner");
+
+        let operation_hash = Self::operation_hash(b"set_veto_power", &wallet, (owner.clone(), enabled));
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            let key = (wallet.clone(), owner.clone());
+            if enabled {
+                <VetoPower<T>>::insert(&key, true);
+            } else {
+                <VetoPower<T>>::remove(&key);
+            }
+            Self::deposit_event(RawEvent::VetoPowerSet(wallet, owner, enabled));
+        }
+
+        Ok(())
+    }
+
+    // changes the role of an owner, requires quorum
+    fn set_role(origin, wallet: Address<T>, owner: Address<T>, role: Role) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+        let owner = <balances::Module<T>>::lookup(owner)?;
+
+        ensure!(Self::owners(&wallet).contains(&owner), "role holder isn't owner");
+        ensure!(role == Role::Approver || !Self::mandatory(&wallet).contains(&owner), "mandatory signer must be approver");
+        ensure!(role == Role::Approver || !Self::is_stage_member(&wallet, &owner), "stage member must be approver");
+        ensure!(role == Role::Approver || !Self::is_rule_approver(&wallet, &owner), "rule approver must be approver");
+
+        let key = (wallet.clone(), owner.clone());
+        let mut approvers = Self::signs_count(&Self::approvers_mask(&wallet));
+        if Self::role(&key) == Role::Approver {
+            approvers -= 1;
+        }
+        if role == Role::Approver {
+            approvers += 1;
+        }
+        Self::ensure_quorum_reachable(&wallet, Self::owners(&wallet).len() as u64, approvers)?;
+
+        let operation_hash = Self::operation_hash(b"set_role", &wallet, (owner.clone(), role));
+        if Self::approve_management(&wallet, &who, operation_hash)? {
+            if role == Role::Approver {
+                <Roles<T>>::remove(&key);
+            } else {
+                <Roles<T>>::insert(&key, role);
+            }
+            // observers can't spend from the wallet
+            if role == Role::Observer && <Allowances<T>>::exists(&key) {
+                <Allowances<T>>::remove(&key);
+                Self::deposit_event(RawEvent::AllowanceSet(wallet.clone(), owner.clone(), Zero::zero(), Zero::zero()));
+            }
+            Self::deposit_event(RawEvent::RoleSet(wallet, owner, role));
+        }
+
+        Ok(())
+    }
+
+    // cancels a queued withdrawal or migration during its delay, a single veto holder may do it
+    fn veto(origin, wallet: Address<T>, operation: T::Hash) -> Result {
+        let who = ensure_signed(origin)?;
+        let wallet = <balances::Module<T>>::lookup(wallet)?;
+
+        Self::owner_index(&wallet, &who)?;
+        ensure!(Self::has_veto_power((wallet.clone(), who.clone())), "sender has no veto power");
+
+        if let Some(migration) = Self::queued_migration(&wallet).filter(|migration| migration.operation == operation) {
+            <Migrations<T>>::remove(&wallet);
+            <MigrationsAt<T>>::mutate(migration.execute_at, |wallets| wallets.retain(|existing| *existing != wallet));
+        } else {
+            let queued = Self::queued(operation).ok_or("operation isn't queued")?;
+            ensure!(queued.wallet == wallet, "operation isn't queued");
+
+            Self::dequeue(operation, &queued);
+        }
+        Self::deposit_event(RawEvent::Vetoed(wallet, operation, who));
+
+        Ok(())
+    }
+
+    // executes queued withdrawals and migrations whose delay has passed and cancels expired withdrawals
+    fn on_finalise(n: T::BlockNumber) {
+        // fees paid by wallets for transactions which haven't signed their operations are charged back,
+        // owners who can't repay them pay their fees again
+        for (owner, wallet, fee) in <SponsoredFees<T>>::take() {
+            if <balances::Module<T>>::transfer_without_sign(owner.clone(), wallet.clone().into(), fee).is_ok() {
+                <FeeBudget<T>>::mutate(&wallet, |budget| *budget = *budget + fee);
+                Self::deposit_event(RawEvent::FeeChargedBack(wallet, owner, fee));
+            } else {
+                <FeePayer<T>>::remove(&owner);
+                Self::deposit_event(RawEvent::FeePayerSet(owner, None));
+            }
+        }
+
+        for operation in <QueuedAt<T>>::take(n) {
+            if let Some(queued) = Self::queued(operation) {
+                // skips withdrawals queued again for a later block
+                if queued.execute_at <= n {
+                    Self::execute_queued(operation, queued);
+                }
+            }
+        }
+
+        for wallet in <MigrationsAt<T>>::take(n) {
+            // migrations of a frozen wallet execute once it is unfrozen
+            if !Self::frozen(&wallet) {
+                if let Some(migration) = Self::queued_migration(&wallet).filter(|migration| migration.execute_at <= n) {
+                    <Migrations<T>>::remove(&wallet);
+                    Self::execute_migration(&wallet, migration);
+                }
+            }
+        }
+
+        for lock in <LocksExpiring<T>>::take(n) {
+            if let Some(locked) = <Locks<T>>::take(lock) {
+                Self::refund_lock(lock, locked);
+            }
+        }
+
+        for operation in <Expiring<T>>::take(n) {
+            if let Some(pending) = Self::pending(operation) {
+                if pending.expires_at == n {
+                    Self::deposit_event(RawEvent::Expired(pending.wallet.clone(), operation));
+                    Self::cancel_pending(operation, pending);
+                }
+            }
+        }
+    }
+
+    // deposits are made using balances.transfer(to_wallet: T::AccountId, value: T::Balance)
+  }
+}
+
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Multisig {
+	    // Total number of multisig wallets
+	    pub GlobalNonce get(global_nonce): u64;
+
+	    // List of owners for each multisig
+		pub Owners get(owners): map T::AccountId => Vec<T::AccountId>;
+
+		// Owners who haven't accepted their invitations yet, the multisig is inactive until all accept
+		pub Invitations get(invitations): map T::AccountId => Vec<T::AccountId>;
+
+		// Creator of each multisig pending activation
+		pub Creator get(creator): map T::AccountId => Option<T::AccountId>;
+
+		// Deposit reserved from the creator of each multisig pending activation
+		pub CreationDeposit get(creation_deposit): map T::AccountId => T::Balance;
+
+		// Signatures quorum for each multisig
+		pub Signatures get(signatures_required): map T::AccountId => u64;
+
+		// Signatures quorum by withdrawal value for each multisig, as ascending (maximal value, signatures) pairs
+		pub Tiers get(tiers): map T::AccountId => Vec<(T::Balance, u64)>;
+
+		// Share of owners required to sign for each multisig, if its quorum follows owners changes
+		pub Threshold get(threshold): map T::AccountId => Option<Permill>;
+
+		// Number of owners changes of each multisig, invalidates pending operations
+		pub OwnersVersion get(owners_version): map T::AccountId => u64;
+
+		// Ordered approval stages of withdrawals for each multisig
+		pub Stages get(stages): map T::AccountId => Vec<Stage<T::AccountId>>;
+
+		// Index of the current approval stage of operations of each multisig
+		pub OperationStage get(operation_stage): map (T::AccountId, T::Hash) => u32;
+
+		// Spending policy rules of each multisig, the first matching one applies
+		pub Policy get(policy): map T::AccountId => Vec<Rule<T::AccountId, T::Balance, T::BlockNumber>>;
+
+		// Owners which have to sign every withdrawal of the multisig
+		pub Mandatory get(mandatory): map T::AccountId => Vec<T::AccountId>;
+
+		// Bitmask of signatures for operations of each multisig
+		// Operation is Hash(operation_name, wallet id, owners version, operation parameters)
+		pub OperationBitmask get(operation_bitmask): map (T::AccountId, T::Hash) => u64;
+
+		// Destinations allowlisted for each multisig
+		pub Allowlist get(allowlist): map T::AccountId => Vec<T::AccountId>;
+
+		// Whether withdrawals are restricted to allowlisted destinations
+		pub AllowlistOnly get(allowlist_only): map T::AccountId => bool;
+
+		// Lower signatures quorum for withdrawals to allowlisted destinations, zero if unset
+		pub AllowlistSignatures get(allowlist_signatures_required): map T::AccountId => u64;
+
+		// Role of the owner, keyed by (wallet, owner)
+		pub Roles get(role): map (T::AccountId, T::AccountId) => Role;
+
+		// Number of sub-vaults opened for each multisig
+		pub VaultCount get(vault_count): map T::AccountId => u32;
+
+		// Whether the multisig is frozen by one of its owners
+		pub Frozen get(frozen): map T::AccountId => bool;
+
+		// Queued withdrawals of each frozen multisig which came due, executed once it is unfrozen
+		pub Suspended get(suspended): map T::AccountId => Vec<T::Hash>;
+
+		// Number of blocks withdrawals of each multisig wait after gathering the quorum
+		pub Delay get(delay): map T::AccountId => T::BlockNumber;
+
+		// Whether the owner may veto queued withdrawals, keyed by (wallet, owner)
+		pub VetoPower get(has_veto_power): map (T::AccountId, T::AccountId) => bool;
+
+		// Operations gathering signatures in each multisig with their proposers
+		pub Proposals get(proposal): map (T::AccountId, T::Hash) => Option<Proposal<T::AccountId, T::Balance>>;
+
+		// Operations gathering signatures in each multisig
+		pub Proposed get(proposed): map T::AccountId => Vec<T::Hash>;
+
+		// Maximum number of operations gathering signatures in each multisig, zero if unlimited
+		pub MaxProposals get(max_proposals): map T::AccountId => u32;
+
+		// Maximum number of operations gathering signatures proposed by each owner of each multisig, zero if unlimited
+		pub MaxOwnerProposals get(max_owner_proposals): map T::AccountId => u32;
+
+		// Deposit reserved from the proposer of each operation of each multisig
+		pub ProposalDeposit get(proposal_deposit): map T::AccountId => T::Balance;
+
+		// Operation is Hash(operation_name, wallet id, owners version, operation parameters),
+		// bitmask of the owners of the multisig who have rejected it
+		pub Rejections get(rejections): map (T::AccountId, T::Hash) => u64;
+
+		// Withdrawals gathering signatures, keyed by operation
+		pub Pending get(pending): map T::Hash => Option<PendingWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>>;
+
+		// Pending and queued withdrawals of each multisig
+		pub Operations get(operations): map T::AccountId => Vec<T::Hash>;
+
+		// Pending withdrawals expiring at each block
+		pub Expiring get(expiring): map T::BlockNumber => Vec<T::Hash>;
+
+		// Number of blocks withdrawals of each multisig may gather signatures, zero if unlimited
+		pub Lifetime get(lifetime): map T::AccountId => T::BlockNumber;
+
+		// Amount each multisig may still spend paying transaction fees of its owners
+		pub FeeBudget get(fee_budget): map T::AccountId => T::Balance;
+
+		// Multisig paying the transaction fees of each owner who has chosen one
+		pub FeePayer get(fee_payer): map T::AccountId => Option<T::AccountId>;
+
+		// Fees paid by multisigs in the current block as (owner, multisig, fee), until the owner signs an operation
+		pub SponsoredFees get(sponsored_fees): Vec<(T::AccountId, T::AccountId, T::Balance)>;
+
+		// Maximum fee refunded to each approver of an executed operation of each multisig
+		pub RefundLimit get(refund_limit): map T::AccountId => T::Balance;
+
+		// Whether amounts of new withdrawals of each multisig are reserved
+		pub ReserveMode get(reserve_mode): map T::AccountId => bool;
+
+		// Operations depending on each operation
+		pub Dependents get(dependents): map T::Hash => Vec<T::Hash>;
+
+		// Whether each withdrawal of a wallet has executed, kept while its dependents wait
+		pub Executed get(executed): map (T::AccountId, T::Hash) => bool;
+
+		// Amounts locked against hashes, keyed by operation
+		pub Locks get(hash_lock): map T::Hash => Option<HashLock<T::AccountId, T::Balance, T::BlockNumber, T::Hash>>;
+
+		// Locks timing out at each block
+		pub LocksExpiring get(locks_expiring): map T::BlockNumber => Vec<T::Hash>;
+
+		// Successor of each migrated multisig
+		pub Successor get(successor): map T::AccountId => Option<T::AccountId>;
+
+		// Migration of each multisig waiting for its delay to pass
+		pub Migrations get(queued_migration): map T::AccountId => Option<QueuedMigration<T::AccountId, T::BlockNumber, T::Hash>>;
+
+		// Multisigs whose migration is queued for execution at the block
+		pub MigrationsAt get(migrations_at): map T::BlockNumber => Vec<T::AccountId>;
+
+		// Withdrawals waiting for their delay to pass, keyed by operation
+		pub Queue get(queued): map T::Hash => Option<QueuedWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>>;
+
+		// Operations queued for execution at the block
+		pub QueuedAt get(queued_at): map T::BlockNumber => Vec<T::Hash>;
+
+		// Allowance of each owner, keyed by (wallet, owner)
+		pub Allowances get(allowance): map (T::AccountId, T::AccountId) => Allowance<T::Balance, T::BlockNumber>;
+	}
+	add_extra_genesis {
+		config(_marker): ::std::marker::PhantomData<T>;
+		build(|_, _, _| {});
+	}
+}
+
+
+decl_event!(
+	pub enum Event<T> where
+	    AccountId = <T as system::Trait>::AccountId,
+		Hash = <T as system::Trait>::Hash,
+		BlockNumber = <T as system::Trait>::BlockNumber,
+		Balance = <T as balances::Trait>::Balance
+	{
+		/// Created new wallet identified by Runtime::AccountId type.
+		Created(AccountId),
+		/// Withdrawal from the wallet (wallet, destination, value, memo).
+		Withdraw(AccountId, AccountId, Balance, Vec<u8>),
+		/// Owner was invited to the wallet pending activation (wallet, owner).
+		Invited(AccountId, AccountId),
+		/// Owner accepted the invitation (wallet, owner).
+		InvitationAccepted(AccountId, AccountId),
+		/// All owners accepted their invitations and the wallet became active.
+		Activated(AccountId),
+		/// Wallet pending activation was cancelled by its creator.
+		WalletCancelled(AccountId),
+		/// Sub-vault was opened for the wallet (wallet, vault index, vault account).
+		VaultOpened(AccountId, u32, AccountId),
+		/// Withdrawal from a sub-vault (wallet, vault index, destination, value, memo).
+		VaultWithdraw(AccountId, u32, AccountId, Balance, Vec<u8>),
+		/// Destination was added to the wallet allowlist (wallet, destination).
+		AllowlistAdded(AccountId, AccountId),
+		/// Destination was removed from the wallet allowlist (wallet, destination).
+		AllowlistRemoved(AccountId, AccountId),
+		/// Allowlist-only mode was switched for the wallet.
+		AllowlistOnlySet(AccountId, bool),
+		/// Quorum for allowlisted destinations was changed for the wallet.
+		AllowlistSignaturesSet(AccountId, u64),
+		/// Fee budget was set (wallet, budget).
+		FeeBudgetSet(AccountId, Balance),
+		/// Transaction fee of the owner was refunded (wallet, owner, fee).
+		FeeRefunded(AccountId, AccountId, Balance),
+		/// Wallet paying the transaction fees of the owner was chosen (owner, wallet).
+		FeePayerSet(AccountId, Option<AccountId>),
+		/// Transaction fee of the owner's signature was paid by the wallet (wallet, owner, fee).
+		FeePaid(AccountId, AccountId, Balance),
+		/// Transaction fee paid by the wallet was charged back to the owner (wallet, owner, fee).
+		FeeChargedBack(AccountId, AccountId, Balance),
+		/// Refund limit was set (wallet, limit).
+		RefundLimitSet(AccountId, Balance),
+		/// Proposal limits were set (wallet, max proposals, max proposals per owner, deposit).
+		ProposalLimitsSet(AccountId, u32, u32, Balance),
+		/// Owner has approved the operation by its hash (wallet, operation, owner).
+		Approved(AccountId, Hash, AccountId),
+		/// Owner has rejected the operation (wallet, operation, owner).
+		Rejected(AccountId, Hash, AccountId),
+		/// Operation was cancelled as it can't gather the quorum anymore (wallet, operation).
+		Defeated(AccountId, Hash),
+		/// Owner has commented the operation (wallet, operation, owner, comment).
+		Commented(AccountId, Hash, AccountId, Vec<u8>),
+		/// Withdrawal was amended (wallet, operation, amended operation, revision, old destination,
+		/// old amount, new destination, new amount).
+		Amended(AccountId, Hash, Hash, u32, AccountId, Balance, AccountId, Balance),
+		/// Reserve mode was set (wallet, enabled).
+		ReserveModeSet(AccountId, bool),
+		/// Withdrawals lifetime was set (wallet, blocks).
+		LifetimeSet(AccountId, BlockNumber),
+		/// Approved withdrawal waits for its prerequisite to execute (wallet, operation, prerequisite).
+		Blocked(AccountId, Hash, Hash),
+		/// Amount was locked for the counterparty (wallet, lock, counterparty, value, hashlock, timeout).
+		Locked(AccountId, Hash, AccountId, Balance, Hash, BlockNumber),
+		/// Locked amount was claimed by the counterparty (wallet, lock, preimage).
+		Claimed(AccountId, Hash, Vec<u8>),
+		/// Locked amount returned to the wallet after the timeout (wallet, lock).
+		LockRefunded(AccountId, Hash),
+		/// Pending withdrawal has expired (wallet, operation).
+		Expired(AccountId, Hash),
+		/// Wallet was migrated to the successor (wallet, successor).
+		Migrated(AccountId, AccountId),
+		/// Migration gathered the quorum and waits for execution (wallet, operation, execution block).
+		MigrationQueued(AccountId, Hash, BlockNumber),
+		/// Owner was added to the wallet (wallet, owner).
+		OwnerAdded(AccountId, AccountId),
+		/// Owner was removed from the wallet (wallet, owner).
+		OwnerRemoved(AccountId, AccountId),
+		/// Signatures quorum was changed for the wallet.
+		SignaturesSet(AccountId, u64),
+		/// Quorum of the wallet follows the share of owners.
+		ThresholdSet(AccountId, Permill),
+		/// Amount tiers of the quorum were changed for the wallet.
+		TiersSet(AccountId),
+		/// Approval stages were changed for the wallet.
+		StagesSet(AccountId),
+		/// Operation passed the approval stage (wallet, operation, stage index).
+		StageCompleted(AccountId, Hash, u32),
+		/// Spending policy was changed for the wallet.
+		PolicySet(AccountId),
+		/// Owner was granted an allowance (wallet, owner, limit, period).
+		AllowanceSet(AccountId, AccountId, Balance, BlockNumber),
+		/// Owner spent its allowance (wallet, owner, destination, value).
+		AllowanceSpent(AccountId, AccountId, AccountId, Balance),
+		/// Wallet was frozen by the owner (wallet, owner).
+		Frozen(AccountId, AccountId),
+		/// Wallet was unfrozen by quorum.
+		Unfrozen(AccountId),
+		/// Queued withdrawal came due while the wallet is frozen and waits for unfreezing (wallet, operation).
+		Suspended(AccountId, Hash),
+		/// Role of the owner was changed (wallet, owner, role).
+		RoleSet(AccountId, AccountId, Role),
+		/// Withdrawal delay was changed for the wallet.
+		DelaySet(AccountId, BlockNumber),
+		/// Veto power of the owner was switched (wallet, owner).
+		VetoPowerSet(AccountId, AccountId, bool),
+		/// Withdrawal gathered the quorum and waits for execution (wallet, operation, execution block).
+		Queued(AccountId, Hash, BlockNumber),
+		/// Queued withdrawal was vetoed (wallet, operation, veto holder).
+		Vetoed(AccountId, Hash, AccountId),
+		/// Queued withdrawal was cancelled (wallet, operation).
+		Cancelled(AccountId, Hash),
+	}
+);
+
+
+impl<T: Trait> Module<T> {
+    // PUBLIC IMMUTABLES
+
+    /// The combined balance of `who`.
+    pub fn signs_count(bitmask: &u64) -> u64 {
+        let mut count = 0;
+        let mut mask = *bitmask;
+        while mask > 0 {
+            if mask & 1 == 1 {
+                count += 1;
+            }
+            mask >>= 1;
+        }
+
+        return count;
+    }
+
+    /// Account of the `index` sub-vault of `wallet`: Hash(wallet id, index).
+    /// Vault 0 is the wallet itself.
+    pub fn vault_account(wallet: &T::AccountId, index: u32) -> T::AccountId {
+        if index == 0 {
+            return wallet.clone();
+        }
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&wallet.encode());
+        buf.extend_from_slice(&index.encode());
+        let h: T::Hash = T::Hashing::hash(&buf[..]);
+
+        T::AccountId::decode(&mut &h.encode()[..]).unwrap()
+    }
+
+    /// Balance of the `index` sub-vault of `wallet`.
+    pub fn vault_balance(wallet: &T::AccountId, index: u32) -> T::Balance {
+        <balances::Module<T>>::free_balance(Self::vault_account(wallet, index))
+    }
+
+    /// Bitmask of the owners of `wallet` whose signatures count towards the quorum.
+    pub fn approvers_mask(wallet: &T::AccountId) -> u64 {
+        Self::owners(wallet).iter().enumerate()
+            .filter(|&(_, owner)| Self::role((wallet.clone(), owner.clone())) == Role::Approver)
+            .fold(0, |mask, (index, _)| mask | (1 << index))
+    }
+
+    /// Signatures quorum of `owners` owners for the `threshold` share of them.
+    ///
+    /// Strictly more than `threshold` of the owners have to sign, i.e. the quorum is
+    /// `floor(threshold * owners) + 1`, but never more than all the owners. E.g. 50% of
+    /// 4 owners is 3 signatures and 2/3 of 3 owners, expressed as 666_666 millionths, is 2.
+    pub fn relative_signatures_required(threshold: Permill, owners: u64) -> u64 {
+        (threshold * owners + 1).min(owners)
+    }
+
+    /// Number of signatures required to withdraw `value` from `wallet`, according to its amount tiers.
+    pub fn withdrawal_signatures_required(wallet: &T::AccountId, value: T::Balance) -> u64 {
+        let signatures_required = Self::signatures_required(wallet);
+
+        Self::tiers(wallet).into_iter()
+            .find(|&(limit, _)| value <= limit)
+            .map_or(signatures_required, |(_, signatures)| signatures.min(signatures_required))
+    }
+
+    /// Bitmask of the owners of `wallet` who have to sign every withdrawal.
+    pub fn mandatory_mask(wallet: &T::AccountId) -> u64 {
+        Self::members_mask(&Self::owners(wallet), &Self::mandatory(wallet))
+    }
+
+    /// Bitmask of `members` positions among `owners`.
+    pub fn members_mask(owners: &[T::AccountId], members: &[T::AccountId]) -> u64 {
+        owners.iter().enumerate()
+            .filter(|&(_, owner)| members.contains(owner))
+            .fold(0, |mask, (index, _)| mask | (1 << index))
+    }
+
+    /// Whether `owner` approves withdrawals at any stage of `wallet`.
+    pub fn is_stage_member(wallet: &T::AccountId, owner: &T::AccountId) -> bool {
+        Self::stages(wallet).iter().any(|stage| stage.owners.contains(owner))
+    }
+
+    /// Whether all mandatory owners of `wallet` are present in `bitmask`.
+    pub fn has_mandatory_signatures(wallet: &T::AccountId, bitmask: u64) -> bool {
+        let mandatory_mask = Self::mandatory_mask(wallet);
+        bitmask & mandatory_mask == mandatory_mask
+    }
+
+    /// Number of signatures in `bitmask` which count towards the quorum of `wallet`.
+    pub fn approvals(wallet: &T::AccountId, bitmask: u64) -> u64 {
+        Self::signs_count(&(bitmask & Self::approvers_mask(wallet)))
+    }
+
+    // PRIVATE HELPERS
+
+    /// Registers a new wallet of `sender` and returns its id.
+    fn create_wallet(
+        sender: &T::AccountId,
+        owners: Vec<Address<T>>,
+        signatures_required: u64,
+        mandatory: Vec<Address<T>>
+    ) -> result::Result<T::AccountId, &'static str> {
+        let (owners, mandatory) = Self::check_owners(owners, signatures_required, mandatory)?;
+
+        Ok(Self::register_wallet(sender, owners, signatures_required, mandatory))
+    }
+
+    /// Resolves and validates owners and mandatory signers of a new wallet.
+    fn check_owners(
+        owners: Vec<Address<T>>,
+        signatures_required: u64,
+        mandatory: Vec<Address<T>>
+    ) -> result::Result<(Vec<T::AccountId>, Vec<T::AccountId>), &'static str> {
+        let mut owners = owners.into_iter().map(<balances::Module<T>>::lookup).collect::<result::Result<Vec<_>, _>>()?;
+        let mandatory = mandatory.into_iter().map(<balances::Module<T>>::lookup).collect::<result::Result<Vec<_>, _>>()?;
+
+        if 0 == owners.len() || owners.len() > 64 {
+            return Err("invalid number of owners");
+        }
+
+        // owners are stored in canonical order
+        owners.sort();
+        ensure!(owners.windows(2).all(|pair| pair[0] != pair[1]), "duplicate owner");
+
+        if 0 == signatures_required || signatures_required > owners.len() as u64 {
+            return Err("invalid number of signatures");
+        }
+        for (i, signer) in mandatory.iter().enumerate() {
+            ensure!(owners.contains(signer), "mandatory signer isn't owner");
+            ensure!(!mandatory[..i].contains(signer), "duplicate mandatory signer");
+        }
+        ensure!(mandatory.len() as u64 <= signatures_required, "too many mandatory signers");
+
+        Ok((owners, mandatory))
+    }
+
+    /// Stores a new wallet with validated owners. The wallet id is derived from the
+    /// `creator` and the global nonce.
+    fn register_wallet(
+        creator: &T::AccountId,
+        owners: Vec<T::AccountId>,
+        signatures_required: u64,
+        mandatory: Vec<T::AccountId>
+    ) -> T::AccountId {
+        let this_nonce: u64 = Self::global_nonce();
+        <GlobalNonce<T>>::mutate(|nonce| *nonce += 1);
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&creator.encode());
+        buf.extend_from_slice(&this_nonce.encode());
+        let h: T::Hash = T::Hashing::hash(&buf[..]);
+
+        let wallet_id = T::AccountId::decode(&mut &h.encode()[..]).unwrap();
+
+        <Owners<T>>::insert(&wallet_id, owners);
+        <Signatures<T>>::insert(&wallet_id, signatures_required);
+        if !mandatory.is_empty() {
+            <Mandatory<T>>::insert(&wallet_id, mandatory);
+        }
+
+        Self::deposit_event(RawEvent::Created(wallet_id.clone()));
+
+        wallet_id
+    }
+
+    /// Returns the deposit reserved for `wallet` pending activation to its creator.
+    fn release_creation_deposit(wallet: &T::AccountId) {
+        if let Some(creator) = <Creator<T>>::take(wallet) {
+            <balances::Module<T>>::unreserve(&creator, <CreationDeposit<T>>::take(wallet));
+        }
+    }
+
+    /// Identifier of an operation: Hash(operation_name, wallet id, owners version, operation parameters).
+    fn operation_hash<P: Encode>(name: &[u8], wallet: &T::AccountId, parameters: P) -> T::Hash {
+        let mut buf = Vec::new();
+        buf.append(&mut name.encode());
+        buf.append(&mut wallet.encode());
+        buf.append(&mut Self::owners_version(wallet).encode());
+        buf.append(&mut parameters.encode());
+
+        T::Hashing::hash(&buf[..])
+    }
+
+    /// Identifier of the withdrawal of `value` from the `vault` of `wallet` to `to`
+    /// with `memo`, approved by owners by hash.
+    pub fn withdrawal_hash(
+        wallet: &T::AccountId,
+        vault: u32,
+        to: &T::AccountId,
+        value: T::Balance,
+        memo: &[u8],
+        after: Option<T::Hash>,
+        window: &ExecutionWindow<T::BlockNumber, T::Balance>
+    ) -> T::Hash {
+        Self::operation_hash(b"withdraw", wallet, (vault, to.clone(), value, memo.to_vec(), after, window.clone()))
+    }
+
+    /// Identifier of the lock of `value` of `wallet` for `to` against `hashlock` until
+    /// `timeout`, approved by owners by hash.
+    pub fn lock_hash(wallet: &T::AccountId, to: &T::AccountId, value: T::Balance, hashlock: T::Hash, timeout: T::BlockNumber) -> T::Hash {
+        Self::operation_hash(b"lock", wallet, (to.clone(), value, hashlock, timeout))
+    }
+
+    /// Identifier of the pending `withdrawal` when it belongs to `wallet`.
+    fn pending_hash(wallet: &T::AccountId, withdrawal: &PendingWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>) -> T::Hash {
+        match withdrawal.lock {
+            Some((hashlock, timeout)) => Self::lock_hash(wallet, &withdrawal.to, withdrawal.value, hashlock, timeout),
+            None => Self::withdrawal_hash(
+                wallet, withdrawal.vault, &withdrawal.to, withdrawal.value, &withdrawal.memo, withdrawal.after, &withdrawal.window
+            ),
+        }
+    }
+
+    /// Operation which has to execute before the withdrawal `operation`.
+    pub fn prerequisite(operation: T::Hash) -> Option<T::Hash> {
+        match Self::pending(operation) {
+            Some(pending) => pending.after,
+            None => Self::queued(operation).and_then(|queued| queued.after),
+        }
+    }
+
+    /// Withdrawals waiting for `operation` to execute.
+    pub fn dependents_of(operation: T::Hash) -> Vec<T::Hash> {
+        Self::dependents(operation).into_iter()
+            .filter(|dependent| <Pending<T>>::exists(dependent) || <Queue<T>>::exists(dependent))
+            .collect()
+    }
+
+    /// Position of `who` among the owners of `wallet`.
+    fn owner_index(wallet: &T::AccountId, who: &T::AccountId) -> result::Result<usize, &'static str> {
+        ensure!(Self::successor(wallet).is_none(), "wallet is migrated");
+        ensure!(<Owners<T>>::exists(wallet), "wallet doesn't exists");
+
+        Self::owners(wallet).iter().position(|owner| owner == who).ok_or("sender isn't owner")
+    }
+
+    /// Adds the signature of `who` to `operation` and returns the resulting bitmask.
+    /// `management` operations aren't subject to the wallet cap of pending operations.
+    fn sign(wallet: &T::AccountId, who: &T::AccountId, operation: T::Hash, management: bool) -> result::Result<u64, &'static str> {
+        let index = Self::owner_index(wallet, who)?;
+        ensure!(Self::invitations(wallet).is_empty(), "wallet isn't active");
+
+        let key = (wallet.clone(), operation);
+        let bitmask = Self::operation_bitmask(&key);
+        ensure!((bitmask & (1 << index)) == 0, "sender already signed");
+
+        match Self::role((wallet.clone(), who.clone())) {
+            Role::Approver => {},
+            Role::Proposer => ensure!(bitmask == 0, "sender can only propose operations"),
+            Role::Observer => return Err("sender is observer"),
+        }
+
+        if bitmask == 0 && !<Proposals<T>>::exists(&key) {
+            Self::open_proposal(wallet, who, operation, management)?;
+        }
+
+        let bitmask = bitmask | (1 << index);
+        <OperationBitmask<T>>::insert(&key, bitmask);
+        // an approval withdraws the rejection of the sender
+        let rejections = Self::rejections(&key);
+        if (rejections & (1 << index)) != 0 {
+            <Rejections<T>>::insert(&key, rejections & !(1 << index));
+        }
+        Self::settle_fee(wallet, who);
+
+        Ok(bitmask)
+    }
+
+    /// Owners of `wallet` who have signed an operation with `bitmask`.
+    fn signers(wallet: &T::AccountId, bitmask: u64) -> Vec<T::AccountId> {
+        Self::owners(wallet).into_iter().enumerate()
+            .filter(|&(index, _)| bitmask & (1 << index) != 0)
+            .map(|(_, owner)| owner)
+            .collect()
+    }
+
+    /// Refunds the transaction base fee, up to the wallet refund limit, to each of the
+    /// `approvers` of an executed operation of `wallet`.
+    fn refund_approvers(wallet: &T::AccountId, approvers: &[T::AccountId]) {
+        let fee = <balances::Module<T>>::transaction_base_fee();
+        let limit = Self::refund_limit(wallet);
+        let refund = if fee < limit { fee } else { limit };
+        if refund.is_zero() {
+            return;
+        }
+
+        for approver in approvers {
+            if <balances::Module<T>>::transfer_without_sign(wallet.clone(), approver.clone().into(), refund).is_err() {
+                break;
+            }
+            Self::deposit_event(RawEvent::FeeRefunded(wallet.clone(), approver.clone(), refund));
+        }
+    }
+
+    /// Records `operation` proposed by `who`, within the wallet caps, and reserves
+    /// the proposal deposit. Management operations are exempt from the wallet cap, so
+    /// that an owner filling it can still be removed, but count toward the owner cap.
+    fn open_proposal(wallet: &T::AccountId, who: &T::AccountId, operation: T::Hash, management: bool) -> Result {
+        let proposed = Self::proposed(wallet);
+
+        let max_proposals = Self::max_proposals(wallet);
+        ensure!(management || max_proposals == 0 || (proposed.len() as u32) < max_proposals, "too many pending operations");
+
+        let max_owner_proposals = Self::max_owner_proposals(wallet);
+        if max_owner_proposals > 0 {
+            let owner_proposals = proposed.iter()
+                .filter(|existing| Self::proposal((wallet.clone(), **existing)).map_or(false, |proposal| proposal.proposer == *who))
+                .count() as u32;
+            ensure!(owner_proposals < max_owner_proposals, "too many pending operations of sender");
+        }
+
+        let deposit = Self::proposal_deposit(wallet);
+        if !deposit.is_zero() {
+            Self::reserve_funds(who, deposit)?;
+        }
+
+        <Proposals<T>>::insert((wallet.clone(), operation), Proposal { wallet: wallet.clone(), proposer: who.clone(), deposit });
+        <Proposed<T>>::mutate(wallet, |proposed| proposed.push(operation));
+
+        Ok(())
+    }
+
+    /// Removes the approvals and rejections of `operation` of `wallet`.
+    fn clear_votes(wallet: &T::AccountId, operation: T::Hash) {
+        let key = (wallet.clone(), operation);
+        <OperationBitmask<T>>::remove(&key);
+        <OperationStage<T>>::remove(&key);
+        <Rejections<T>>::remove(&key);
+    }
+
+    /// Removes the record of `operation` of `wallet` and returns the deposit to its proposer.
+    fn close_proposal(wallet: &T::AccountId, operation: T::Hash) {
+        if let Some(proposal) = <Proposals<T>>::take((wallet.clone(), operation)) {
+            <Proposed<T>>::mutate(wallet, |proposed| proposed.retain(|existing| *existing != operation));
+            if !proposal.deposit.is_zero() {
+                <balances::Module<T>>::unreserve(&proposal.proposer, proposal.deposit);
+            }
+        }
+    }
+
+    /// Moves the record of `operation`, whose parameters have changed, to `hash`.
+    fn rekey_proposal(wallet: &T::AccountId, operation: T::Hash, hash: T::Hash) {
+        if let Some(proposal) = <Proposals<T>>::take((wallet.clone(), operation)) {
+            <Proposals<T>>::insert((wallet.clone(), hash), proposal);
+            <Proposed<T>>::mutate(wallet, |proposed| {
+                for existing in proposed.iter_mut().filter(|existing| **existing == operation) {
+                    *existing = hash;
+                }
+            });
+        }
+    }
+
+    /// Closes the proposals of `wallet` which aren't pending withdrawals anymore, after
+    /// an owners change or migration has invalidated their approvals.
+    fn close_proposals(wallet: &T::AccountId) {
+        for operation in Self::proposed(wallet) {
+            if !<Pending<T>>::exists(operation) {
+                Self::clear_votes(wallet, operation);
+                Self::close_proposal(wallet, operation);
+            }
+        }
+    }
+
+    /// Keeps the transaction fee `wallet` has paid for `who` in the current block, once
+    /// `who` has signed an operation of the wallet.
+    fn settle_fee(wallet: &T::AccountId, who: &T::AccountId) {
+        let mut fees = Self::sponsored_fees();
+        if let Some(position) = fees.iter().position(|&(ref owner, ref payer, _)| owner == who && payer == wallet) {
+            let (_, _, fee) = fees.remove(position);
+            <SponsoredFees<T>>::put(fees);
+            Self::deposit_event(RawEvent::FeePaid(wallet.clone(), who.clone(), fee));
+        }
+    }
+
+    /// Signs `operation` unless `who` has already approved it by hash, in which case the
+    /// caller reveals the operation parameters. Returns the bitmask and whether `who`
+    /// had signed before.
+    fn sign_or_reveal(wallet: &T::AccountId, who: &T::AccountId, operation: T::Hash, management: bool) -> result::Result<(u64, bool), &'static str> {
+        let index = Self::owner_index(wallet, who)?;
+        let bitmask = Self::operation_bitmask((wallet.clone(), operation));
+
+        if bitmask & (1 << index) != 0 {
+            Ok((bitmask, true))
+        } else {
+            Self::sign(wallet, who, operation, management).map(|bitmask| (bitmask, false))
+        }
+    }
+
+    /// Signs a wallet management operation. Returns `true` once the operation has
+    /// gathered the wallet quorum, or satisfied the matching policy rule, and should
+    /// be applied by the caller.
+    fn approve_management(wallet: &T::AccountId, who: &T::AccountId, operation: T::Hash) -> result::Result<bool, &'static str> {
+        ensure!(!Self::frozen(wallet), "wallet is frozen");
+
+        Self::approve_frozen_management(wallet, who, operation)
+    }
+
+    /// Signs a wallet management operation which may be applied while the wallet is
+    /// frozen, like `approve_management` otherwise.
+    fn approve_frozen_management(wallet: &T::AccountId, who: &T::AccountId, operation: T::Hash) -> result::Result<bool, &'static str> {
+        let (bitmask, signed) = Self::sign_or_reveal(wallet, who, operation, true)?;
+
+        let approved = Self::management_approved(wallet, bitmask);
+        ensure!(approved || !signed, "sender already signed");
+
+        if approved {
+            Self::clear_votes(wallet, operation);
+            Self::close_proposal(wallet, operation);
+            Self::refund_approvers(wallet, &Self::signers(wallet, bitmask));
+        }
+
+        Ok(approved)
+    }
+
+    /// Checks that a wallet with `owners` owners, `approvers` of whom count towards
+    /// the quorum, can still gather its quorum.
+    fn ensure_quorum_reachable(wallet: &T::AccountId, owners: u64, approvers: u64) -> Result {
+        let signatures_required = match Self::threshold(wallet) {
+            Some(threshold) => Self::relative_signatures_required(threshold, owners),
+            None => Self::signatures_required(wallet),
+        };
+
+        ensure!(signatures_required <= approvers, "not enough approvers left");
+        ensure!(Self::mandatory(wallet).len() as u64 <= signatures_required, "too many mandatory signers");
+
+        Ok(())
+    }
+
+    /// Replaces the owners of `wallet`. Pending approvals are reset, since the owners
+    /// positions in bitmasks change, and the relative quorum, if any, is recomputed.
+    fn change_owners(wallet: &T::AccountId, owners: Vec<T::AccountId>) {
+        let count = owners.len() as u64;
+        <Owners<T>>::insert(wallet, owners);
+        <OwnersVersion<T>>::mutate(wallet, |version| *version += 1);
+        Self::carry_operations(wallet, wallet);
+        Self::close_proposals(wallet);
+
+        if let Some(threshold) = Self::threshold(wallet) {
+            let signatures_required = Self::relative_signatures_required(threshold, count);
+            if signatures_required != Self::signatures_required(wallet) {
+                <Signatures<T>>::insert(wallet, signatures_required);
+                Self::deposit_event(RawEvent::SignaturesSet(wallet.clone(), signatures_required));
+            }
+        }
+    }
+
+    /// First rule of the `wallet` policy whose conditions the operation meets.
+    pub fn matching_rule(
+        wallet: &T::AccountId,
+        kind: CallKind,
+        destination: Option<&T::AccountId>,
+        value: Option<T::Balance>
+    ) -> Option<Rule<T::AccountId, T::Balance, T::BlockNumber>> {
+        let now = <system::Module<T>>::block_number();
+
+        Self::policy(wallet).into_iter().find(|rule| rule.conditions.iter().all(|condition| match *condition {
+            Condition::Call(call) => call == kind,
+            Condition::MinValue(min) => value.map_or(false, |value| value >= min),
+            Condition::MaxValue(max) => value.map_or(false, |value| value <= max),
+            Condition::DestinationIn(ref accounts) => destination.map_or(false, |destination| accounts.contains(destination)),
+            Condition::Window(from, until) => from <= now && now < until,
+        }))
+    }
+
+    /// Whether `owner` is a required approver in any rule of the `wallet` policy.
+    pub fn is_rule_approver(wallet: &T::AccountId, owner: &T::AccountId) -> bool {
+        Self::policy(wallet).iter().any(|rule| rule.approvers.contains(owner))
+    }
+
+    /// Whether `bitmask` satisfies the policy `rule` of `wallet`. Mandatory signers
+    /// are required by every rule.
+    fn rule_satisfied(wallet: &T::AccountId, rule: &Rule<T::AccountId, T::Balance, T::BlockNumber>, bitmask: u64) -> bool {
+        let required_mask = Self::members_mask(&Self::owners(wallet), &rule.approvers);
+
+        Self::approvals(wallet, bitmask) >= rule.signatures_required
+            && bitmask & required_mask == required_mask
+            && Self::has_mandatory_signatures(wallet, bitmask)
+    }
+
+    /// Whether `bitmask` gathers the quorum of `wallet`, with its amount tiers and
+    /// allowlist, and mandatory signatures for a withdrawal of `value` to `to`.
+    fn withdrawal_quorum_met(wallet: &T::AccountId, to: &T::AccountId, value: T::Balance, bitmask: u64) -> bool {
+        let mut signatures_required = Self::withdrawal_signatures_required(wallet, value);
+        let allowlist_signatures = Self::allowlist_signatures_required(wallet);
+        if allowlist_signatures > 0 && allowlist_signatures < signatures_required && Self::allowlist(wallet).contains(to) {
+            signatures_required = allowlist_signatures;
+        }
+
+        Self::approvals(wallet, bitmask) >= signatures_required && Self::has_mandatory_signatures(wallet, bitmask)
+    }
+
+    /// Whether `bitmask` approves a wallet management operation of `wallet`. The quorum
+    /// and mandatory signatures are always required, the matching policy rule may only
+    /// add to them.
+    fn management_approved(wallet: &T::AccountId, bitmask: u64) -> bool {
+        Self::approvals(wallet, bitmask) >= Self::signatures_required(wallet)
+            && Self::has_mandatory_signatures(wallet, bitmask)
+            && Self::matching_rule(wallet, CallKind::Management, None, None).map_or(true, |rule| Self::rule_satisfied(wallet, &rule, bitmask))
+    }
+
+    /// Whether `bitmask` approves the withdrawal `operation` of `wallet` of `value` to `to`,
+    /// by the matching policy rule, the remaining approval stages or the quorum.
+    fn withdrawal_approvable(wallet: &T::AccountId, operation: T::Hash, to: &T::AccountId, value: T::Balance, bitmask: u64) -> bool {
+        if let Some(rule) = Self::matching_rule(wallet, CallKind::Withdraw, Some(to), Some(value)) {
+            return Self::rule_satisfied(wallet, &rule, bitmask);
+        }
+
+        let stages = Self::stages(wallet);
+        if stages.is_empty() {
+            Self::withdrawal_quorum_met(wallet, to, value, bitmask)
+        } else {
+            Self::next_stage(wallet, operation, &stages, bitmask) == stages.len() && Self::has_mandatory_signatures(wallet, bitmask)
+        }
+    }
+
+    /// Stage `operation` currently waits for. Operations started before the stages were
+    /// shortened wait for the last one.
+    fn current_stage<'a>(wallet: &T::AccountId, operation: T::Hash, stages: &'a [Stage<T::AccountId>]) -> &'a Stage<T::AccountId> {
+        let index = Self::operation_stage((wallet.clone(), operation)) as usize;
+        &stages[index.min(stages.len() - 1)]
+    }
+
+    /// Index of the first stage of `wallet`, from the one `operation` waits for, whose
+    /// quorum, or mandatory signatures of its members, `bitmask` doesn't meet. Equals
+    /// the number of stages once all are passed.
+    fn next_stage(wallet: &T::AccountId, operation: T::Hash, stages: &[Stage<T::AccountId>], bitmask: u64) -> usize {
+        let owners = Self::owners(wallet);
+        let approvals = bitmask & Self::approvers_mask(wallet);
+        let mandatory_mask = Self::mandatory_mask(wallet);
+
+        let mut index = (Self::operation_stage((wallet.clone(), operation)) as usize).min(stages.len() - 1);
+        while index < stages.len() {
+            let stage = &stages[index];
+            let members_mask = Self::members_mask(&owners, &stage.owners);
+            let stage_mandatory_mask = members_mask & mandatory_mask;
+            if Self::signs_count(&(approvals & members_mask)) < stage.signatures_required
+                || approvals & stage_mandatory_mask != stage_mandatory_mask {
+                break;
+            }
+            index += 1;
+        }
+
+        index
+    }
+
+    /// Moves `operation` through the stages of `wallet` whose quorum is met by `bitmask`.
+    /// Returns `true` once the last stage is passed.
+    fn advance_stages(wallet: &T::AccountId, operation: T::Hash, stages: &[Stage<T::AccountId>], bitmask: u64) -> bool {
+        let key = (wallet.clone(), operation);
+        let current = (Self::operation_stage(&key) as usize).min(stages.len() - 1);
+        let index = Self::next_stage(wallet, operation, stages, bitmask);
+        for completed in current..index {
+            Self::deposit_event(RawEvent::StageCompleted(wallet.clone(), operation, completed as u32));
+        }
+
+        if index < stages.len() {
+            <OperationStage<T>>::insert(&key, index as u32);
+            false
+        } else {
+            <OperationStage<T>>::remove(&key);
+            true
+        }
+    }
+
+    /// Signs the withdrawal of `value` from the `vault` of `wallet` to `to`, locked for `to`
+    /// if `lock` is set, and makes, or queues, the withdrawal once it is approved.
+    fn request_withdrawal(
+        who: T::AccountId,
+        wallet: T::AccountId,
+        vault: u32,
+        to: T::AccountId,
+        value: T::Balance,
+        memo: Vec<u8>,
+        after: Option<T::Hash>,
+        window: ExecutionWindow<T::BlockNumber, T::Balance>,
+        lock: Option<(T::Hash, T::BlockNumber)>
+    ) -> Result {
+        ensure!(!Self::frozen(&wallet), "wallet is frozen");
+
+        let now = <system::Module<T>>::block_number();
+        // the block the withdrawal window closes at, zero if it doesn't close
+        let closes_at = if window.latest.is_zero() {
+            Zero::zero()
+        } else {
+            ensure!(window.earliest <= window.latest, "invalid withdrawal window");
+            ensure!(now <= window.latest, "withdrawal window has closed");
+            window.latest.checked_add(&One::one()).ok_or("invalid withdrawal window")?
+        };
+
+        let allowlisted = Self::allowlist(&wallet).contains(&to);
+        ensure!(allowlisted || !Self::allowlist_only(&wallet), "destination isn't allowlisted");
+
+        ensure!(vault <= Self::vault_count(&wallet), "vault doesn't exist");
+
+        let operation_hash = match lock {
+            Some((hashlock, timeout)) => Self::lock_hash(&wallet, &to, value, hashlock, timeout),
+            None => Self::withdrawal_hash(&wallet, vault, &to, value, &memo, after, &window),
+        };
+        ensure!(!<Queue<T>>::exists(operation_hash), "operation is already queued");
+        if let Some(prerequisite) = after {
+            ensure!(
+                <Pending<T>>::exists(operation_hash) || Self::operations(&wallet).contains(&prerequisite)
+                    || Self::executed((wallet.clone(), prerequisite)),
+                "prerequisite doesn't exist"
+            );
+        }
+
+        let rule = Self::matching_rule(&wallet, CallKind::Withdraw, Some(&to), Some(value));
+        let stages = if rule.is_none() { Self::stages(&wallet) } else { Vec::new() };
+        if !stages.is_empty() {
+            let stage = Self::current_stage(&wallet, operation_hash, &stages);
+            ensure!(stage.owners.contains(&who), "sender isn't in the current stage");
+        }
+        // veto holders may block payments, but not initiate them
+        ensure!(
+            <Pending<T>>::exists(operation_hash) || !Self::has_veto_power((wallet.clone(), who.clone())),
+            "veto holder can't initiate payments"
+        );
+
+        // checked before signing, so a failed reservation doesn't consume a fee refund
+        if Self::reserve_mode(&wallet) && !<Pending<T>>::exists(operation_hash) {
+            Self::ensure_can_reserve(&Self::vault_account(&wallet, vault), value)?;
+        }
+
+        // the withdrawal expires after its lifetime or once its window closes
+        let lifetime = Self::lifetime(&wallet);
+        let mut expires_at = if lifetime.is_zero() {
+            Zero::zero()
+        } else {
+            now.checked_add(&lifetime).ok_or("lifetime is too long")?
+        };
+        if !closes_at.is_zero() && (expires_at.is_zero() || closes_at < expires_at) {
+            expires_at = closes_at;
+        }
+        let delay = rule.as_ref().map_or_else(|| Self::delay(&wallet), |rule| rule.delay);
+        let mut execute_at = now.checked_add(&delay).ok_or("delay is too long")?;
+        if window.earliest > execute_at {
+            execute_at = window.earliest;
+        }
+
+        let (bitmask, signed) = Self::sign_or_reveal(&wallet, &who, operation_hash, false)?;
+        let revealed = signed && !<Pending<T>>::exists(operation_hash);
+
+        let reserved = match Self::pending(operation_hash) {
+            Some(pending) => pending.reserved,
+            None => {
+                let reserved = Self::reserve_mode(&wallet);
+                if reserved {
+                    if let Err(err) = Self::reserve_funds(&Self::vault_account(&wallet, vault), value) {
+                        Self::clear_votes(&wallet, operation_hash);
+                        Self::close_proposal(&wallet, operation_hash);
+                        return Err(err);
+                    }
+                }
+
+                if !expires_at.is_zero() {
+                    <Expiring<T>>::mutate(expires_at, |operations| operations.push(operation_hash));
+                }
+
+                <Pending<T>>::insert(operation_hash, PendingWithdrawal {
+                    wallet: wallet.clone(),
+                    vault,
+                    to: to.clone(),
+                    value,
+                    memo: memo.clone(),
+                    after,
+                    window: window.clone(),
+                    lock,
+                    proposer: who.clone(),
+                    reserved,
+                    expires_at,
+                    revision: 0,
+                });
+                <Operations<T>>::mutate(&wallet, |operations| operations.push(operation_hash));
+                if let Some(prerequisite) = after {
+                    <Dependents<T>>::mutate(prerequisite, |dependents| dependents.push(operation_hash));
+                }
+                reserved
+            },
+        };
+
+        let approved = if let Some(ref rule) = rule {
+            Self::rule_satisfied(&wallet, rule, bitmask)
+        } else if stages.is_empty() {
+            Self::withdrawal_quorum_met(&wallet, &to, value, bitmask)
+        } else {
+            Self::advance_stages(&wallet, operation_hash, &stages, bitmask) && Self::has_mandatory_signatures(&wallet, bitmask)
+        };
+        // an approver may repeat the call to execute a withdrawal approved by hash
+        ensure!(approved || !signed || revealed, "sender already signed");
+
+        if approved {
+            let blocked = after.map_or(false, |prerequisite| !Self::prerequisite_met(&wallet, prerequisite));
+            let ready = execute_at == now && !blocked;
+            // a withdrawal whose conditions, like the minimum balance, aren't met yet waits
+            // for them in the queue
+            if ready && Self::check_window(&wallet, vault, value, reserved, &window).is_err() {
+                execute_at = now.checked_add(&One::one()).ok_or("balance would fall below the minimum")?;
+            }
+            if ready && execute_at == now {
+                Self::pay_or_lock(operation_hash, &wallet, vault, &to, value, reserved, memo, lock)?;
+                Self::clear_votes(&wallet, operation_hash);
+                Self::close_proposal(&wallet, operation_hash);
+                Self::refund_approvers(&wallet, &Self::signers(&wallet, bitmask));
+                <Pending<T>>::remove(operation_hash);
+                Self::forget_operation(&wallet, operation_hash);
+                Self::resolve_dependency(&wallet, operation_hash, after);
+                Self::complete(&wallet, operation_hash);
+            } else {
+                Self::clear_votes(&wallet, operation_hash);
+                Self::close_proposal(&wallet, operation_hash);
+                let proposer = <Pending<T>>::take(operation_hash).map_or(who, |pending| pending.proposer);
+
+                // a withdrawal still waiting when its window closes is cancelled
+                if !closes_at.is_zero() {
+                    <QueuedAt<T>>::mutate(closes_at, |operations| operations.push(operation_hash));
+                }
+                <Queue<T>>::insert(operation_hash, QueuedWithdrawal {
+                    wallet: wallet.clone(),
+                    vault,
+                    to,
+                    value,
+                    memo,
+                    after,
+                    window,
+                    lock,
+                    proposer,
+                    reserved,
+                    approvers: Self::signers(&wallet, bitmask),
+                    execute_at,
+                });
+                match after {
+                    // executes once the prerequisite does
+                    Some(prerequisite) if execute_at == now => {
+                        Self::deposit_event(RawEvent::Blocked(wallet, operation_hash, prerequisite));
+                    },
+                    _ => {
+                        <QueuedAt<T>>::mutate(execute_at, |operations| operations.push(operation_hash));
+                        Self::deposit_event(RawEvent::Queued(wallet, operation_hash, execute_at));
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amount of a timed out lock to its wallet, or to the successor of a
+    /// migrated wallet.
+    fn refund_lock(lock: T::Hash, locked: HashLock<T::AccountId, T::Balance, T::BlockNumber, T::Hash>) {
+        <balances::Module<T>>::unreserve(&locked.wallet, locked.value);
+        if let Some(successor) = Self::successor(&locked.wallet) {
+            let _ = <balances::Module<T>>::transfer_without_sign(locked.wallet.clone(), successor.into(), locked.value);
+        }
+
+        Self::deposit_event(RawEvent::LockRefunded(locked.wallet, lock));
+    }
+
+    /// Checks that a withdrawal of `value` from the `vault` of `wallet` may execute
+    /// in the current block.
+    fn check_window(
+        wallet: &T::AccountId,
+        vault: u32,
+        value: T::Balance,
+        reserved: bool,
+        window: &ExecutionWindow<T::BlockNumber, T::Balance>
+    ) -> Result {
+        let now = <system::Module<T>>::block_number();
+        ensure!(now >= window.earliest, "withdrawal window hasn't opened");
+        ensure!(window.latest.is_zero() || now <= window.latest, "withdrawal window has closed");
+
+        // the reserved amount isn't part of the free balance anymore
+        let spent = if reserved { Zero::zero() } else { value };
+        let required = spent.checked_add(&window.min_balance).ok_or("balance would fall below the minimum")?;
+        ensure!(Self::vault_balance(wallet, vault) >= required, "balance would fall below the minimum");
+
+        Ok(())
+    }
+
+    /// Transfers `value` from the `vault` of `wallet` to `to`, out of the reserved
+    /// balance of the vault if the withdrawal is `reserved`.
+    fn pay(wallet: &T::AccountId, vault: u32, to: &T::AccountId, value: T::Balance, reserved: bool, memo: Vec<u8>) -> Result {
+        let from = Self::vault_account(wallet, vault);
+        if reserved {
+            Self::repatriate(&from, to, value)?;
+        } else {
+            <balances::Module<T>>::transfer_without_sign(from, to.clone().into(), value)?;
+        }
+
+        if vault == 0 {
+            Self::deposit_event(RawEvent::Withdraw(wallet.clone(), to.clone(), value, memo));
+        } else {
+            Self::deposit_event(RawEvent::VaultWithdraw(wallet.clone(), vault, to.clone(), value, memo));
+        }
+
+        Ok(())
+    }
+
+    /// Pays the withdrawal `operation` of `wallet`, or places its amount in a lock for `to`
+    /// if the withdrawal has a hash `lock`.
+    fn pay_or_lock(
+        operation: T::Hash,
+        wallet: &T::AccountId,
+        vault: u32,
+        to: &T::AccountId,
+        value: T::Balance,
+        reserved: bool,
+        memo: Vec<u8>,
+        lock: Option<(T::Hash, T::BlockNumber)>
+    ) -> Result {
+        let (hashlock, timeout) = match lock {
+            Some(lock) => lock,
+            None => return Self::pay(wallet, vault, to, value, reserved, memo),
+        };
+
+        // a lock whose delay outlasts its timeout is cancelled instead
+        ensure!(<system::Module<T>>::block_number() < timeout, "timeout has passed");
+        let refund_at = timeout.checked_add(&One::one()).ok_or("invalid timeout")?;
+        if !reserved {
+            Self::reserve_funds(wallet, value)?;
+        }
+
+        <Locks<T>>::insert(operation, HashLock { wallet: wallet.clone(), to: to.clone(), value, hashlock, timeout });
+        <LocksExpiring<T>>::mutate(refund_at, |locks| locks.push(operation));
+        Self::deposit_event(RawEvent::Locked(wallet.clone(), operation, to.clone(), value, hashlock, timeout));
+
+        Ok(())
+    }
+
+    /// Checks that `value` may be reserved on `who`. The balances module burns a reserved
+    /// balance falling below the existential deposit, and reaps such a free balance, so every
+    /// reservation has to reach it and leave at least as much free.
+    fn ensure_can_reserve(who: &T::AccountId, value: T::Balance) -> Result {
+        let existential_deposit = <balances::Module<T>>::existential_deposit();
+        ensure!(value >= existential_deposit, "amount is below the existential deposit");
+        let required = value.checked_add(&existential_deposit).ok_or("not enough free funds")?;
+        ensure!(<balances::Module<T>>::free_balance(who) >= required, "not enough free funds");
+
+        Ok(())
+    }
+
+    /// Reserves `value` on `who`, see `ensure_can_reserve`.
+    fn reserve_funds(who: &T::AccountId, value: T::Balance) -> Result {
+        Self::ensure_can_reserve(who, value)?;
+        <balances::Module<T>>::reserve(who, value)
+    }
+
+    /// Moves `value` reserved on `from` to the free balance of `to`, creating the account of `to`
+    /// if it doesn't exist.
+    fn repatriate(from: &T::AccountId, to: &T::AccountId, value: T::Balance) -> Result {
+        ensure!(<balances::Module<T>>::reserved_balance(from) >= value, "not enough reserved funds");
+
+        if <balances::Module<T>>::total_balance(to).is_zero() {
+            // the balances module repatriates to existing accounts only
+            <balances::Module<T>>::unreserve(from, value);
+            if let Err(err) = <balances::Module<T>>::transfer_without_sign(from.clone(), to.clone().into(), value) {
+                // can't fail, the amount has just been unreserved
+                let _ = <balances::Module<T>>::reserve(from, value);
+                return Err(err);
+            }
+        } else if <balances::Module<T>>::repatriate_reserved(from, to, value)?.is_some() {
+            return Err("not enough reserved funds");
+        }
+
+        Ok(())
+    }
+
+    /// Removes a queued withdrawal before its execution.
+    fn dequeue(operation: T::Hash, queued: &QueuedWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>) {
+        <Queue<T>>::remove(operation);
+        <QueuedAt<T>>::mutate(queued.execute_at, |operations| operations.retain(|existing| *existing != operation));
+        <Suspended<T>>::mutate(&queued.wallet, |operations| operations.retain(|existing| *existing != operation));
+        Self::forget_operation(&queued.wallet, operation);
+        Self::resolve_dependency(&queued.wallet, operation, queued.after);
+        Self::release(&queued.wallet, queued.vault, queued.value, queued.reserved);
+        Self::cancel_dependents(operation);
+    }
+
+    /// Removes a withdrawal gathering signatures and releases its reserved amount.
+    /// Callers deposit the event telling why the withdrawal was removed.
+    fn cancel_pending(operation: T::Hash, pending: PendingWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>) {
+        <Pending<T>>::remove(operation);
+        Self::clear_votes(&pending.wallet, operation);
+        Self::close_proposal(&pending.wallet, operation);
+        Self::forget_operation(&pending.wallet, operation);
+        Self::resolve_dependency(&pending.wallet, operation, pending.after);
+        Self::release(&pending.wallet, pending.vault, pending.value, pending.reserved);
+        Self::cancel_dependents(operation);
+    }
+
+    /// Records that `operation` of `wallet` has executed and executes the approved
+    /// withdrawals which were waiting for it. The record is kept only while other
+    /// dependents still wait.
+    fn complete(wallet: &T::AccountId, operation: T::Hash) {
+        let dependents = <Dependents<T>>::take(operation);
+        if dependents.is_empty() {
+            return;
+        }
+        let key = (wallet.clone(), operation);
+        <Executed<T>>::insert(&key, true);
+
+        let now = <system::Module<T>>::block_number();
+        for &dependent in &dependents {
+            if let Some(queued) = Self::queued(dependent) {
+                // withdrawals still waiting for their delay execute in the block hook
+                if queued.execute_at <= now {
+                    Self::execute_queued(dependent, queued);
+                }
+            }
+        }
+
+        let waiting: Vec<T::Hash> = dependents.into_iter()
+            .filter(|dependent| <Pending<T>>::exists(dependent) || <Queue<T>>::exists(dependent))
+            .collect();
+        if waiting.is_empty() {
+            <Executed<T>>::remove(&key);
+        } else {
+            <Dependents<T>>::insert(operation, waiting);
+        }
+    }
+
+    /// Whether the `prerequisite` of a withdrawal of `wallet` has executed, and hasn't
+    /// been proposed again since.
+    fn prerequisite_met(wallet: &T::AccountId, prerequisite: T::Hash) -> bool {
+        Self::executed((wallet.clone(), prerequisite)) && !Self::operations(wallet).contains(&prerequisite)
+    }
+
+    /// Removes `operation` of `wallet`, which has executed or won't execute, from the
+    /// dependents of its prerequisite, and forgets that the prerequisite has executed
+    /// once no dependent waits for it.
+    fn resolve_dependency(wallet: &T::AccountId, operation: T::Hash, after: Option<T::Hash>) {
+        if let Some(prerequisite) = after {
+            if <Dependents<T>>::exists(prerequisite) {
+                let mut dependents = Self::dependents(prerequisite);
+                dependents.retain(|existing| *existing != operation);
+                if dependents.is_empty() {
+                    <Dependents<T>>::remove(prerequisite);
+                    <Executed<T>>::remove((wallet.clone(), prerequisite));
+                } else {
+                    <Dependents<T>>::insert(prerequisite, dependents);
+                }
+            }
+        }
+    }
+
+    /// Cancels the withdrawals depending on `operation`, which won't execute.
+    fn cancel_dependents(operation: T::Hash) {
+        for dependent in <Dependents<T>>::take(operation) {
+            if let Some(pending) = Self::pending(dependent) {
+                let wallet = pending.wallet.clone();
+                Self::cancel_pending(dependent, pending);
+                Self::deposit_event(RawEvent::Cancelled(wallet, dependent));
+            } else if let Some(queued) = Self::queued(dependent) {
+                Self::dequeue(dependent, &queued);
+                Self::deposit_event(RawEvent::Cancelled(queued.wallet, dependent));
+            }
+        }
+    }
+
+    /// Updates the dependency graph after the withdrawal `operation` was re-keyed as `hash`.
+    fn rename_dependency(operation: T::Hash, hash: T::Hash) {
+        if let Some(prerequisite) = Self::prerequisite(hash) {
+            <Dependents<T>>::mutate(prerequisite, |dependents| {
+                for existing in dependents.iter_mut().filter(|existing| **existing == operation) {
+                    *existing = hash;
+                }
+            });
+        }
+
+        let dependents = <Dependents<T>>::take(operation);
+        for dependent in dependents.iter() {
+            <Pending<T>>::mutate(dependent, |pending| {
+                if let Some(pending) = pending.as_mut() {
+                    pending.after = Some(hash);
+                }
+            });
+            <Queue<T>>::mutate(dependent, |queued| {
+                if let Some(queued) = queued.as_mut() {
+                    queued.after = Some(hash);
+                }
+            });
+        }
+        if !dependents.is_empty() {
+            <Dependents<T>>::insert(hash, dependents);
+        }
+    }
+
+    /// Unreserves the amount of a withdrawal which won't be made.
+    fn release(wallet: &T::AccountId, vault: u32, value: T::Balance, reserved: bool) {
+        if reserved {
+            <balances::Module<T>>::unreserve(&Self::vault_account(wallet, vault), value);
+        }
+    }
+
+    /// Removes `operation` from the outstanding withdrawals of `wallet`.
+    fn forget_operation(wallet: &T::AccountId, operation: T::Hash) {
+        <Operations<T>>::mutate(wallet, |operations| operations.retain(|existing| *existing != operation));
+    }
+
+    /// Moves the pending withdrawals of `wallet` to `successor`, which may be the wallet
+    /// itself after an owners change, resetting their approvals. Queued withdrawals of a
+    /// migrated wallet return to pending, the successor owners have to approve them again.
+    fn carry_operations(wallet: &T::AccountId, successor: &T::AccountId) {
+        let mut carried = Vec::new();
+
+        for operation in <Operations<T>>::take(wallet) {
+            let withdrawal = if let Some(pending) = <Pending<T>>::take(operation) {
+                Self::clear_votes(wallet, operation);
+                pending
+            } else if let Some(queued) = Self::queued(operation) {
+                if wallet == successor {
+                    carried.push(operation);
+                    continue;
+                }
+                <Queue<T>>::remove(operation);
+                <QueuedAt<T>>::mutate(queued.execute_at, |operations| operations.retain(|existing| *existing != operation));
+                PendingWithdrawal {
+                    wallet: queued.wallet,
+                    vault: queued.vault,
+                    to: queued.to,
+                    value: queued.value,
+                    memo: queued.memo,
+                    after: queued.after,
+                    window: queued.window,
+                    lock: queued.lock,
+                    proposer: queued.proposer,
+                    reserved: queued.reserved,
+                    expires_at: Zero::zero(),
+                    revision: 0,
+                }
+            } else {
+                continue;
+            };
+
+            // reservations stay on the wallet, the successor reserves again after the move
+            if wallet != successor {
+                Self::release(wallet, withdrawal.vault, withdrawal.value, withdrawal.reserved);
+            }
+
+            let hash = Self::pending_hash(successor, &withdrawal);
+            if <Pending<T>>::exists(hash) {
+                if wallet == successor {
+                    Self::release(wallet, withdrawal.vault, withdrawal.value, withdrawal.reserved);
+                }
+                continue;
+            }
+            if !withdrawal.expires_at.is_zero() {
+                <Expiring<T>>::mutate(withdrawal.expires_at, |operations| operations.push(hash));
+            }
+            // the successor keeps waiting on a prerequisite the wallet has executed
+            if let Some(prerequisite) = withdrawal.after {
+                if wallet != successor && <Executed<T>>::take((wallet.clone(), prerequisite)) {
+                    <Executed<T>>::insert((successor.clone(), prerequisite), true);
+                }
+            }
+            <Pending<T>>::insert(hash, PendingWithdrawal { wallet: successor.clone(), ..withdrawal });
+            Self::rename_dependency(operation, hash);
+            carried.push(hash);
+
+            // the proposer keeps the withdrawal, and its deposit, after an owners change
+            if wallet == successor {
+                Self::rekey_proposal(wallet, operation, hash);
+            }
+        }
+
+        <Operations<T>>::mutate(successor, |operations| operations.extend(carried));
+    }
+
+    /// Transfers the whole free balance of `from` to `to`, less the transfer fee.
+    /// Balances which can't cover the fee, or create the destination account, are left.
+    fn transfer_all(from: &T::AccountId, to: &T::AccountId) {
+        let balance = <balances::Module<T>>::free_balance(from);
+        let would_create = <balances::Module<T>>::free_balance(to).is_zero();
+        let fee = if would_create {
+            <balances::Module<T>>::creation_fee()
+        } else {
+            <balances::Module<T>>::transfer_fee()
+        };
+
+        if balance <= fee || (would_create && balance - fee < <balances::Module<T>>::existential_deposit()) {
+            return;
+        }
+
+        // can't fail after the checks above
+        let _ = <balances::Module<T>>::transfer_without_sign(from.clone(), to.clone().into(), balance - fee);
+    }
+
+    /// Moves `wallet` to a new successor wallet with the owners of the `migration`.
+    fn execute_migration(wallet: &T::AccountId, migration: QueuedMigration<T::AccountId, T::BlockNumber, T::Hash>) {
+        let successor = Self::register_wallet(wallet, migration.owners, migration.signatures_required, migration.mandatory);
+        Self::move_wallet(wallet, &successor);
+        Self::deposit_event(RawEvent::Migrated(wallet.clone(), successor));
+    }
+
+    /// Moves balances, withdrawals and settings independent of the owners from `wallet`
+    /// to `successor`, and leaves a forwarding pointer to the successor.
+    fn move_wallet(wallet: &T::AccountId, successor: &T::AccountId) {
+        Self::carry_operations(wallet, successor);
+        Self::close_proposals(wallet);
+        let vaults = Self::vault_count(wallet);
+        for vault in 0..vaults + 1 {
+            Self::transfer_all(&Self::vault_account(wallet, vault), &Self::vault_account(successor, vault));
+        }
+        for operation in Self::operations(successor) {
+            <Pending<T>>::mutate(operation, |pending| {
+                if let Some(pending) = pending.as_mut() {
+                    pending.reserved = pending.reserved
+                        && Self::reserve_funds(&Self::vault_account(successor, pending.vault), pending.value).is_ok();
+                }
+            });
+        }
+
+        if vaults > 0 {
+            <VaultCount<T>>::insert(successor, vaults);
+        }
+        let allowlist = <Allowlist<T>>::take(wallet);
+        if !allowlist.is_empty() {
+            <Allowlist<T>>::insert(successor, allowlist);
+        }
+        if <AllowlistOnly<T>>::take(wallet) {
+            <AllowlistOnly<T>>::insert(successor, true);
+        }
+        let allowlist_signatures = <AllowlistSignatures<T>>::take(wallet);
+        if allowlist_signatures > 0 && allowlist_signatures <= Self::signatures_required(successor) {
+            <AllowlistSignatures<T>>::insert(successor, allowlist_signatures);
+        }
+        let tiers = <Tiers<T>>::take(wallet);
+        if !tiers.is_empty() {
+            <Tiers<T>>::insert(successor, tiers);
+        }
+        let delay = <Delay<T>>::take(wallet);
+        if !delay.is_zero() {
+            <Delay<T>>::insert(successor, delay);
+        }
+        let lifetime = <Lifetime<T>>::take(wallet);
+        if !lifetime.is_zero() {
+            <Lifetime<T>>::insert(successor, lifetime);
+        }
+        if <ReserveMode<T>>::take(wallet) {
+            <ReserveMode<T>>::insert(successor, true);
+        }
+        let fee_budget = <FeeBudget<T>>::take(wallet);
+        if !fee_budget.is_zero() {
+            <FeeBudget<T>>::insert(successor, fee_budget);
+        }
+        let refund_limit = <RefundLimit<T>>::take(wallet);
+        if !refund_limit.is_zero() {
+            <RefundLimit<T>>::insert(successor, refund_limit);
+        }
+        let max_proposals = <MaxProposals<T>>::take(wallet);
+        if max_proposals > 0 {
+            <MaxProposals<T>>::insert(successor, max_proposals);
+        }
+        let max_owner_proposals = <MaxOwnerProposals<T>>::take(wallet);
+        if max_owner_proposals > 0 {
+            <MaxOwnerProposals<T>>::insert(successor, max_owner_proposals);
+        }
+        let proposal_deposit = <ProposalDeposit<T>>::take(wallet);
+        if !proposal_deposit.is_zero() {
+            <ProposalDeposit<T>>::insert(successor, proposal_deposit);
+        }
+
+        for owner in <Owners<T>>::take(wallet) {
+            let key = (wallet.clone(), owner);
+            <Roles<T>>::remove(&key);
+            <VetoPower<T>>::remove(&key);
+            <Allowances<T>>::remove(&key);
+        }
+        <Signatures<T>>::remove(wallet);
+        <Threshold<T>>::remove(wallet);
+        <Mandatory<T>>::remove(wallet);
+        <Stages<T>>::remove(wallet);
+        <Policy<T>>::remove(wallet);
+        <VaultCount<T>>::remove(wallet);
+
+        <Successor<T>>::insert(wallet, successor.clone());
+    }
+
+    /// Executes a withdrawal whose delay has passed. Withdrawals of a frozen wallet wait
+    /// until it is unfrozen, those whose conditions aren't met wait for them until their
+    /// window closes, those the wallet can't pay for are cancelled.
+    fn execute_queued(operation: T::Hash, queued: QueuedWithdrawal<T::AccountId, T::Balance, T::BlockNumber, T::Hash>) {
+        if Self::frozen(&queued.wallet) {
+            let mut suspended = Self::suspended(&queued.wallet);
+            if !suspended.contains(&operation) {
+                suspended.push(operation);
+                <Suspended<T>>::insert(&queued.wallet, suspended);
+                Self::deposit_event(RawEvent::Suspended(queued.wallet, operation));
+            }
+            return;
+        }
+
+        let now = <system::Module<T>>::block_number();
+        let violated = Self::check_window(&queued.wallet, queued.vault, queued.value, queued.reserved, &queued.window).is_err();
+        // the window doesn't leave another block to wait in
+        let closing = !queued.window.latest.is_zero() && now >= queued.window.latest;
+        if !violated || !closing {
+            if let Some(prerequisite) = queued.after {
+                if !Self::prerequisite_met(&queued.wallet, prerequisite) {
+                    // stays queued until the prerequisite executes
+                    Self::deposit_event(RawEvent::Blocked(queued.wallet, operation, prerequisite));
+                    return;
+                }
+            }
+            if violated {
+                if let Some(next) = now.checked_add(&One::one()) {
+                    // checked again in the next block
+                    <QueuedAt<T>>::mutate(next, |operations| operations.push(operation));
+                    <Queue<T>>::insert(operation, QueuedWithdrawal { execute_at: next, ..queued });
+                    return;
+                }
+            }
+        }
+
+        let QueuedWithdrawal { wallet, vault, to, value, memo, after, lock, reserved, approvers, .. } = queued;
+        <Queue<T>>::remove(operation);
+        Self::forget_operation(&wallet, operation);
+        Self::resolve_dependency(&wallet, operation, after);
+
+        if violated || Self::pay_or_lock(operation, &wallet, vault, &to, value, reserved, memo, lock).is_err() {
+            Self::release(&wallet, vault, value, reserved);
+            Self::deposit_event(RawEvent::Cancelled(wallet, operation));
+            Self::cancel_dependents(operation);
+        } else {
+            Self::refund_approvers(&wallet, &approvers);
+            Self::complete(&wallet, operation);
+        }
+    }
+}
+
+/// Charges the transaction fee of an owner to the wallet the owner has chosen, while its fee
+/// budget and balance cover it, and to the owner otherwise. The call isn't known before dispatch,
+/// a fee the transaction doesn't settle by signing an operation of the wallet is charged back
+/// at the end of the block.
+impl<T: Trait> MakePayment<T::AccountId> for Module<T> {
+    fn make_payment(transactor: &T::AccountId, encoded_len: usize) -> Result {
+        if let Some(wallet) = Self::fee_payer(transactor) {
+            let fee = <balances::Module<T>>::transaction_base_fee()
+                + <balances::Module<T>>::transaction_byte_fee() * <T::Balance as As<u64>>::sa(encoded_len as u64);
+            let budget = Self::fee_budget(&wallet);
+            let balance = <balances::Module<T>>::free_balance(&wallet);
+            let covered = fee.checked_add(&<balances::Module<T>>::existential_deposit()).map_or(false, |required| balance >= required);
+
+            if Self::owners(&wallet).contains(transactor) && budget >= fee && covered {
+                <balances::Module<T>>::set_free_balance(&wallet, balance - fee);
+                <balances::Module<T>>::decrease_total_stake_by(fee);
+                <FeeBudget<T>>::insert(&wallet, budget - fee);
+                <SponsoredFees<T>>::mutate(|fees| fees.push((transactor.clone(), wallet, fee)));
+                return Ok(());
+            }
+        }
+
+        <balances::Module<T> as MakePayment<T::AccountId>>::make_payment(transactor, encoded_len)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate sr_io as runtime_io;
+
+    use super::*;
+
+    use keyring::Keyring;
+    use primitives::{H256, Blake2Hasher};
+    use runtime_primitives::BuildStorage;
+    use runtime_primitives::traits::{BlakeTwo256, OnFinalise};
+    use runtime_primitives::testing::{Digest, DigestItem, Header};
+    use runtime_io::{with_externalities, TestExternalities};
+    use balances::address;
+
+    impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type Digest = Digest;
+        type AccountId = H256;
+        type Header = Header;
+        type Event = ();
+        type Log = DigestItem;
+    }
+
+    impl balances::Trait for Test {
+        type Balance = u64;
+        type AccountIndex = u64;
+        type OnFreeBalanceZero = ();
+        type EnsureAccountLiquid = ();
+        type Event = ();
+    }
+
+    impl super::Trait for Test {
+        type Event = ();
+    }
+
+    type Balances = balances::Module<Test>;
+    type Multisig = Module<Test>;
+    type Address = balances::Address<Test>;
+
+    fn new_test_ext() -> TestExternalities<Blake2Hasher> {
+        new_test_ext_with_existential_deposit(0)
+    }
+
+    fn new_test_ext_with_existential_deposit(existential_deposit: u64) -> TestExternalities<Blake2Hasher> {
+        let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+        t.extend(balances::GenesisConfig::<Test> {
+            _genesis_phantom_data: ::std::marker::PhantomData,
+            balances: vec![(Keyring::Alice.to_raw_public().into(), 100),
+                           (Keyring::Bob.to_raw_public().into(), 99),
+                           (Keyring::Charlie.to_raw_public().into(), 10)],
+            transaction_base_fee: 0,
+            transaction_byte_fee: 0,
+            transfer_fee: 0,
+            creation_fee: 0,
+            existential_deposit,
+            reclaim_rebate: 0,
+        }.build_storage().unwrap().0);
+        t.into()
+    }
+
+    fn address_of(user: Keyring) -> Address {
+        <Address as From<H256>>::from(user.to_raw_public().into())
+    }
+
+    fn signature_of(user: Keyring) -> <Test as system::Trait>::Origin {
+        Some(user.to_raw_public().into()).into()
+    }
+
+    fn account_id_of(user: Address) -> <Test as system::Trait>::AccountId {
+        match user {
+            address::Address::Id(i) => i,
+            address::Address::Index(_) => panic!("invalid account id"),
+        }
+    }
+
+    fn wallet_id_of(creator: Keyring, nonce: u64) -> Address {
+        let creator_addr = account_id_of(address_of(creator));
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&creator_addr.encode());
+        buf.extend_from_slice(&nonce.encode());
+        let h: <Test as system::Trait>::Hash = <Test as system::Trait>::Hashing::hash(&buf[..]);
+
+        return <Test as system::Trait>::AccountId::decode(&mut &h.encode()[..]).unwrap().into();
+    }
+
+    fn new_wallet(creator: Keyring, owners: Vec<Keyring>, signatures_required: u64, balance: u64) -> Address {
+        assert_ok!(Multisig::create(signature_of(creator),
+            owners.into_iter().map(address_of).collect(),
+            signatures_required.into(),
+            vec![]));
+
+        let wallet_id = wallet_id_of(creator, Multisig::global_nonce() - 1);
+
+        if balance > 0 {
+            assert_ok!(Balances::transfer(signature_of(creator), wallet_id.clone().into(), balance.into()));
+        }
+
+        wallet_id
+    }
+
+    fn balance_of(user: Address) -> u64 {
+        Balances::free_balance(account_id_of(user))
+    }
+
+    #[test]
+    fn genesis_nonce() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_eq!(Multisig::global_nonce(), 0);
+        });
+    }
+
+    #[test]
+    fn create() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Multisig::create(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)],
+                2.into(),
+                vec![]));
+
+            assert_eq!(Multisig::signatures_required(account_id_of(wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1))), 2);
+        });
+    }
+
+    #[test]
+    fn create_errors() {
+        with_externalities(&mut new_test_ext(), || {
+            let owners = vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)];
+
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), vec![address_of(Keyring::Alice), address::Address::Index(1000)], 1.into(), vec![]),
+                Err("invalid account index"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 1.into(), vec![address::Address::Index(1000)]),
+                Err("invalid account index"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Alice)], 2.into(), vec![]),
+                Err("duplicate owner"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), vec![], 1.into(), vec![]),
+                Err("invalid number of owners"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice),
+                (0..65u8).map(|i| Address::from(H256::from([i; 32]))).collect(), 1.into(), vec![]),
+                Err("invalid number of owners"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 0.into(), vec![]),
+                Err("invalid number of signatures"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 4.into(), vec![]),
+                Err("invalid number of signatures"));
+            assert_eq!(Multisig::global_nonce(), 0);
+        });
+    }
+
+    #[test]
+    fn create_sorts_owners() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Charlie, Keyring::Alice, Keyring::Bob], 2, 0);
+
+            let mut owners = vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie].into_iter()
+                .map(|user| account_id_of(address_of(user)))
+                .collect::<Vec<_>>();
+            owners.sort();
+
+            assert_eq!(Multisig::owners(account_id_of(wallet_id)), owners);
+        });
+    }
+
+    #[test]
+    fn withdraw() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Multisig::create(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)],
+                2.into(),
+                vec![]
+            ));
+
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice),
                 wallet_id.clone().into(),
                 10.into()
             ));
 
-            assert_eq!(Balances::free_balance(account_id_of(wallet_id.clone())), 10);
-            println!("Wallet balance: {:}", Balances::free_balance(account_id_of(wallet_id.clone())));
+            assert_eq!(Balances::free_balance(account_id_of(wallet_id.clone())), 10);
+            println!("Wallet balance: {:}", Balances::free_balance(account_id_of(wallet_id.clone())));
+
+            let bob_balance_before = Balances::free_balance(account_id_of(address_of(Keyring::Bob)));
+
+            println!("Bob balance before: {:}", bob_balance_before);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice),
+                wallet_id.clone(),
+                address_of(Keyring::Bob),
+                1.into()));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie),
+                wallet_id.clone(),
+                address_of(Keyring::Bob),
+                1.into()));
+
+            let bob_balance_after = Balances::free_balance(account_id_of(address_of(Keyring::Bob)));
+
+            println!("Bob balance after: {:}", bob_balance_after);
+
+            assert_eq!(bob_balance_before + 1, bob_balance_after);
+        });
+    }
+
+    #[test]
+    fn allowlist_only() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::add_allowed_destination(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+                assert_ok!(Multisig::set_allowlist_only(signature_of(owner), wallet_id.clone(), true));
+            }
+
+            assert_eq!(Multisig::allowlist(account_id_of(wallet_id.clone())), vec![account_id_of(address_of(Keyring::Charlie))]);
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("destination isn't allowlisted"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Charlie), 1.into()));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 11);
+        });
+    }
+
+    #[test]
+    fn allowlist_signatures() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+
+            assert_eq!(Multisig::set_allowlist_signatures(signature_of(Keyring::Alice), wallet_id.clone(), 3.into()),
+                Err("invalid number of signatures"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::add_allowed_destination(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+                assert_ok!(Multisig::set_allowlist_signatures(signature_of(owner), wallet_id.clone(), 1.into()));
+            }
+
+            // a single signature is enough for an allowlisted destination
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Charlie), 2.into()));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 12);
+
+            // but not for any other one
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Alice), 2.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::remove_allowed_destination(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+            }
+            assert!(Multisig::allowlist(account_id_of(wallet_id.clone())).is_empty());
+        });
+    }
+
+    #[test]
+    fn allowance() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 20);
+
+            assert_eq!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender has no allowance"));
+            assert_eq!(Multisig::set_allowance(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie), 5.into(), 0),
+                Err("invalid allowance period"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_allowance(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 5.into(), 10));
+            }
+
+            assert_ok!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()),
+                Err("allowance exceeded"));
+            assert_eq!(Multisig::spend_allowance(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender has no allowance"));
+
+            <system::Module<Test>>::set_block_number(10);
+
+            assert_ok!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 14);
+            assert_eq!(balance_of(address_of(Keyring::Bob)), 105);
+
+            // the period ending beyond the last block doesn't elapse
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_allowance(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 5.into(), u64::max_value()));
+            }
+            assert_ok!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            <system::Module<Test>>::set_block_number(1000);
+            assert_eq!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()),
+                Err("allowance exceeded"));
+            assert_eq!(balance_of(wallet_id.clone()), 11);
+
+            // observers lose their allowances
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_role(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), Role::Observer));
+            }
+            assert!(!<Allowances<Test>>::exists(&(account_id_of(wallet_id.clone()), account_id_of(address_of(Keyring::Charlie)))));
+            assert_eq!(Multisig::spend_allowance(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender is observer"));
+            assert_eq!(Multisig::set_allowance(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie), 5.into(), 10),
+                Err("allowance holder is observer"));
+        });
+    }
+
+    #[test]
+    fn freeze() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+
+            assert_eq!(Multisig::freeze(signature_of(Keyring::Dave), wallet_id.clone()), Err("sender isn't owner"));
+            assert_ok!(Multisig::freeze(signature_of(Keyring::Charlie), wallet_id.clone()));
+            assert!(Multisig::frozen(account_id_of(wallet_id.clone())));
+
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("wallet is frozen"));
+            assert_eq!(Multisig::set_allowlist_only(signature_of(Keyring::Bob), wallet_id.clone(), true),
+                Err("wallet is frozen"));
+
+            assert_ok!(Multisig::unfreeze(signature_of(Keyring::Alice), wallet_id.clone()));
+            assert!(Multisig::frozen(account_id_of(wallet_id.clone())));
+            assert_ok!(Multisig::unfreeze(signature_of(Keyring::Bob), wallet_id.clone()));
+            assert!(!Multisig::frozen(account_id_of(wallet_id.clone())));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+
+            // the owner freezing the wallet again can be removed while it is frozen
+            assert_ok!(Multisig::freeze(signature_of(Keyring::Charlie), wallet_id.clone()));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::remove_owner(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+            }
+            assert_eq!(Multisig::owners(account_id_of(wallet_id.clone())).len(), 2);
+            assert_eq!(Multisig::freeze(signature_of(Keyring::Charlie), wallet_id.clone()), Err("sender isn't owner"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::unfreeze(signature_of(owner), wallet_id.clone()));
+            }
+            assert!(!Multisig::frozen(account_id_of(wallet_id.clone())));
+        });
+    }
+
+    #[test]
+    fn veto() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_delay(signature_of(owner), wallet_id.clone(), 5));
+                assert_ok!(Multisig::set_veto_power(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), true));
+            }
+
+            for value in vec![1, 2] {
+                for owner in vec![Keyring::Alice, Keyring::Bob] {
+                    assert_ok!(Multisig::withdraw(signature_of(owner), wallet_id.clone(), address_of(Keyring::Bob), value.into()));
+                }
+            }
+
+            let queued = Multisig::queued_at(5);
+            assert_eq!(queued.len(), 2);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            assert_eq!(Multisig::veto(signature_of(Keyring::Bob), wallet_id.clone(), queued[0]), Err("sender has no veto power"));
+            assert_ok!(Multisig::veto(signature_of(Keyring::Charlie), wallet_id.clone(), queued[0]));
+            assert_eq!(Multisig::queued(queued[0]), None);
+            assert_eq!(Multisig::veto(signature_of(Keyring::Charlie), wallet_id.clone(), queued[0]), Err("operation isn't queued"));
+
+            <Multisig as OnFinalise<u64>>::on_finalise(5);
+
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+            assert_eq!(Multisig::queued(queued[1]), None);
+            assert!(Multisig::queued_at(5).is_empty());
+
+            // the veto holder may approve a payment, but not initiate one
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Charlie), 1.into()),
+                Err("veto holder can't initiate payments"));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Charlie), 1.into()));
+            assert_eq!(Multisig::queued_at(5).len(), 1);
+        });
+    }
+
+    #[test]
+    fn freeze_queued() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            <system::Module<Test>>::set_block_number(1);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_delay(signature_of(owner), wallet_id.clone(), 5));
+            }
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw(signature_of(owner), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            }
+            let operation = Multisig::queued_at(6)[0];
+
+            // the withdrawal coming due while the wallet is frozen waits
+            assert_ok!(Multisig::freeze(signature_of(Keyring::Charlie), wallet_id.clone()));
+            <system::Module<Test>>::set_block_number(6);
+            <Multisig as OnFinalise<u64>>::on_finalise(6);
+            assert!(Multisig::queued(operation).is_some());
+            assert_eq!(Multisig::suspended(&wallet), vec![operation]);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            // and executes once the wallet is unfrozen
+            <system::Module<Test>>::set_block_number(7);
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::unfreeze(signature_of(owner), wallet_id.clone()));
+            }
+            assert_eq!(Multisig::queued(operation), None);
+            assert!(Multisig::suspended(&wallet).is_empty());
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+        });
+    }
+
+    #[test]
+    fn roles() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave], 2, 10);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_role(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), Role::Proposer));
+                assert_ok!(Multisig::set_role(signature_of(owner), wallet_id.clone(), address_of(Keyring::Dave), Role::Observer));
+            }
+            assert_eq!(Multisig::set_role(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), Role::Observer),
+                Err("not enough approvers left"));
+
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Dave), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender is observer"));
+            assert_eq!(Multisig::freeze(signature_of(Keyring::Dave), wallet_id.clone()), Err("sender is observer"));
+
+            // proposal doesn't count towards the quorum
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 2.into()),
+                Err("sender can only propose operations"));
+        });
+    }
+
+    #[test]
+    fn mandatory_signers() {
+        with_externalities(&mut new_test_ext(), || {
+            let owners = vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)];
+
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 2.into(), vec![address_of(Keyring::Dave)]),
+                Err("mandatory signer isn't owner"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 2.into(),
+                vec![address_of(Keyring::Bob), address_of(Keyring::Bob)]), Err("duplicate mandatory signer"));
+            assert_eq!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 1.into(),
+                vec![address_of(Keyring::Bob), address_of(Keyring::Charlie)]), Err("too many mandatory signers"));
+
+            assert_ok!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 2.into(), vec![address_of(Keyring::Charlie)]));
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), wallet_id.clone().into(), 10.into()));
+
+            assert_eq!(Multisig::set_role(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie), Role::Proposer),
+                Err("mandatory signer must be approver"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+
+            // stages have to include the mandatory signer, who signs in its stage
+            let account = |user: Keyring| account_id_of(address_of(user));
+            assert_eq!(Multisig::set_stages(signature_of(Keyring::Alice), wallet_id.clone(),
+                vec![Stage { owners: vec![account(Keyring::Alice), account(Keyring::Bob)], signatures_required: 1 }]),
+                Err("mandatory signer isn't stage member"));
+            let stages = vec![
+                Stage { owners: vec![account(Keyring::Alice), account(Keyring::Charlie)], signatures_required: 1 },
+                Stage { owners: vec![account(Keyring::Bob)], signatures_required: 1 },
+            ];
+            for owner in vec![Keyring::Alice, Keyring::Charlie] {
+                assert_ok!(Multisig::set_stages(signature_of(owner), wallet_id.clone(), stages.clone()));
+            }
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 2.into()),
+                Err("sender isn't in the current stage"));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+        });
+    }
+
+    #[test]
+    fn tiers() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave], 3, 30);
+
+            assert_eq!(Multisig::set_tiers(signature_of(Keyring::Alice), wallet_id.clone(), vec![(8, 2), (5, 1)]),
+                Err("tiers must be ascending"));
+            assert_eq!(Multisig::set_tiers(signature_of(Keyring::Alice), wallet_id.clone(), vec![(5, 1), (8, 4)]),
+                Err("invalid number of signatures"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie] {
+                assert_ok!(Multisig::set_tiers(signature_of(owner), wallet_id.clone(), vec![(5, 1), (8, 2)]));
+            }
+            assert_eq!(Multisig::withdrawal_signatures_required(&account_id_of(wallet_id.clone()), 9), 3);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 5.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 25);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 8.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 8.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 17);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 9.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 9.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 17);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 9.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+        });
+    }
+
+    #[test]
+    fn relative_signatures_required() {
+        assert_eq!(Multisig::relative_signatures_required(Permill::from_percent(50), 4), 3);
+        assert_eq!(Multisig::relative_signatures_required(Permill::from_percent(50), 3), 2);
+        assert_eq!(Multisig::relative_signatures_required(Permill::from_millionths(666_666), 3), 2);
+        assert_eq!(Multisig::relative_signatures_required(Permill::from_percent(100), 5), 5);
+        assert_eq!(Multisig::relative_signatures_required(Permill::from_percent(0), 5), 1);
+    }
+
+    #[test]
+    fn threshold_follows_owners() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 3, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie] {
+                assert_ok!(Multisig::set_threshold(signature_of(owner), wallet_id.clone(), Permill::from_percent(50)));
+            }
+            assert_eq!(Multisig::signatures_required(&wallet), 2);
+
+            // pending approvals are reset by owners changes
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::add_owner(signature_of(owner), wallet_id.clone(), address_of(Keyring::Dave)));
+            }
+            assert_eq!(Multisig::owners(&wallet).len(), 4);
+            assert_eq!(Multisig::signatures_required(&wallet), 3);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Dave), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob, Keyring::Dave] {
+                assert_ok!(Multisig::remove_owner(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+            }
+            assert_eq!(Multisig::owners(&wallet).len(), 3);
+            assert_eq!(Multisig::signatures_required(&wallet), 2);
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender isn't owner"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_signatures_required(signature_of(owner), wallet_id.clone(), 3.into()));
+            }
+            assert_eq!(Multisig::threshold(&wallet), None);
+            assert_eq!(Multisig::signatures_required(&wallet), 3);
+        });
+    }
+
+    #[test]
+    fn stages() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave], 2, 10);
+            let account = |user: Keyring| account_id_of(address_of(user));
+
+            let stages = vec![
+                Stage { owners: vec![account(Keyring::Alice), account(Keyring::Bob)], signatures_required: 1 },
+                Stage { owners: vec![account(Keyring::Charlie), account(Keyring::Dave)], signatures_required: 2 },
+            ];
+
+            assert_eq!(Multisig::set_stages(signature_of(Keyring::Alice), wallet_id.clone(),
+                vec![Stage { owners: vec![account(Keyring::Alice)], signatures_required: 2 }]),
+                Err("invalid number of signatures"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_stages(signature_of(owner), wallet_id.clone(), stages.clone()));
+            }
+            assert_eq!(Multisig::remove_owner(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave)),
+                Err("can't remove stage member"));
+
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender isn't in the current stage"));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender isn't in the current stage"));
+            let operation = Multisig::operations(&account_id_of(wallet_id.clone()))[0];
+            assert_eq!(Multisig::approve(signature_of(Keyring::Bob), wallet_id.clone(), operation, vec![]),
+                Err("sender isn't in the current stage"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Dave), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+        });
+    }
+
+    #[test]
+    fn policy() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 20);
+            let account = |user: Keyring| account_id_of(address_of(user));
+
+            let policy = vec![
+                Rule {
+                    conditions: vec![Condition::Call(CallKind::Withdraw), Condition::MaxValue(5),
+                        Condition::DestinationIn(vec![account(Keyring::Charlie)])],
+                    approvers: vec![],
+                    signatures_required: 1,
+                    delay: 0,
+                },
+                Rule {
+                    conditions: vec![Condition::MinValue(8)],
+                    approvers: vec![account(Keyring::Charlie)],
+                    signatures_required: 2,
+                    delay: 3,
+                },
+                Rule {
+                    conditions: vec![Condition::Call(CallKind::Management)],
+                    approvers: vec![],
+                    signatures_required: 3,
+                    delay: 0,
+                },
+            ];
+
+            assert_eq!(Multisig::set_policy(signature_of(Keyring::Alice), wallet_id.clone(), vec![Rule {
+                conditions: vec![Condition::Window(5, 5)], approvers: vec![], signatures_required: 1, delay: 0,
+            }]), Err("invalid rule window"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_policy(signature_of(owner), wallet_id.clone(), policy.clone()));
+            }
+
+            // small payment to a known destination
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Charlie), 5.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 15);
+
+            // large payment needs Charlie and waits
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 8.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 8.into()));
+            assert!(Multisig::queued_at(3).is_empty());
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 8.into()));
+            assert_eq!(Multisig::queued_at(3).len(), 1);
+
+            // other payments fall back to the wallet quorum
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 6.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 6.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+
+            // management requires everyone
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_allowlist_only(signature_of(owner), wallet_id.clone(), true));
+            }
+            assert!(!Multisig::allowlist_only(account_id_of(wallet_id.clone())));
+            assert_ok!(Multisig::set_allowlist_only(signature_of(Keyring::Charlie), wallet_id.clone(), true));
+            assert!(Multisig::allowlist_only(account_id_of(wallet_id.clone())));
+
+            // but a rule can't relax the quorum for management
+            let relaxed = vec![Rule {
+                conditions: vec![Condition::Call(CallKind::Management)],
+                approvers: vec![],
+                signatures_required: 1,
+                delay: 0,
+            }];
+            for owner in vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie] {
+                assert_ok!(Multisig::set_policy(signature_of(owner), wallet_id.clone(), relaxed.clone()));
+            }
+            assert_ok!(Multisig::set_allowlist_only(signature_of(Keyring::Alice), wallet_id.clone(), false));
+            assert!(Multisig::allowlist_only(account_id_of(wallet_id.clone())));
+            assert_ok!(Multisig::set_allowlist_only(signature_of(Keyring::Bob), wallet_id.clone(), false));
+            assert!(!Multisig::allowlist_only(account_id_of(wallet_id.clone())));
+        });
+    }
+
+    #[test]
+    fn invitations() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Multisig::create_with_invitations(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)], 2.into(), vec![], 5.into()));
+            assert_eq!(Balances::reserved_balance(&account_id_of(address_of(Keyring::Alice))), 5);
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), wallet_id.clone().into(), 10.into()));
+
+            assert_eq!(Multisig::accept_invitation(signature_of(Keyring::Alice), wallet_id.clone()), Err("sender isn't invited"));
+            assert_ok!(Multisig::accept_invitation(signature_of(Keyring::Bob), wallet_id.clone()));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("wallet isn't active"));
+
+            assert_ok!(Multisig::accept_invitation(signature_of(Keyring::Charlie), wallet_id.clone()));
+            assert!(Multisig::invitations(account_id_of(wallet_id.clone())).is_empty());
+            assert_eq!(Balances::reserved_balance(&account_id_of(address_of(Keyring::Alice))), 0);
+            assert_eq!(balance_of(address_of(Keyring::Alice)), 90);
+            assert_eq!(Multisig::cancel_wallet(signature_of(Keyring::Alice), wallet_id.clone()), Err("wallet isn't pending activation"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 9);
+        });
+    }
+
+    #[test]
+    fn cancel_wallet() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_eq!(Multisig::create_with_invitations(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Bob), address_of(Keyring::Charlie)], 1.into(), vec![], 101.into()),
+                Err("not enough free funds"));
+            assert_ok!(Multisig::create_with_invitations(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Bob), address_of(Keyring::Charlie)], 1.into(), vec![], 5.into()));
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Bob), wallet_id.clone().into(), 10.into()));
+            assert_eq!(balance_of(address_of(Keyring::Alice)), 95);
+
+            assert_ok!(Multisig::accept_invitation(signature_of(Keyring::Bob), wallet_id.clone()));
+            assert_eq!(Multisig::cancel_wallet(signature_of(Keyring::Bob), wallet_id.clone()), Err("sender isn't creator"));
+            assert_ok!(Multisig::cancel_wallet(signature_of(Keyring::Alice), wallet_id.clone()));
 
-            let bob_balance_before = Balances::free_balance(account_id_of(address_of(Keyring::Bob)));
+            // the creator recovers the deposit only, the funds of others stay on the wallet
+            assert_eq!(balance_of(address_of(Keyring::Alice)), 100);
+            assert_eq!(Balances::reserved_balance(&account_id_of(address_of(Keyring::Alice))), 0);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+            assert!(Multisig::owners(account_id_of(wallet_id.clone())).is_empty());
+            assert_eq!(Multisig::accept_invitation(signature_of(Keyring::Charlie), wallet_id.clone()), Err("sender isn't invited"));
+        });
+    }
 
-            println!("Bob balance before: {:}", bob_balance_before);
+    #[test]
+    fn vaults() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
 
-            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice),
-                wallet_id.clone(),
-                address_of(Keyring::Bob),
-                1.into()));
+            assert_eq!(Multisig::withdraw_from_vault(signature_of(Keyring::Alice), wallet_id.clone(), 1.into(), address_of(Keyring::Bob), 1.into()),
+                Err("vault doesn't exist"));
 
-            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie),
-                wallet_id.clone(),
-                address_of(Keyring::Bob),
-                1.into()));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::open_vault(signature_of(owner), wallet_id.clone()));
+            }
+            assert_eq!(Multisig::vault_count(&wallet), 1);
+            assert_eq!(Multisig::vault_account(&wallet, 0), wallet);
 
-            let bob_balance_after = Balances::free_balance(account_id_of(address_of(Keyring::Bob)));
+            let vault = Multisig::vault_account(&wallet, 1);
+            assert!(vault != wallet);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), vault.into(), 5.into()));
+            assert_eq!(Multisig::vault_balance(&wallet, 1), 5);
 
-            println!("Bob balance after: {:}", bob_balance_after);
+            for owner in vec![Keyring::Alice, Keyring::Charlie] {
+                assert_ok!(Multisig::withdraw_from_vault(signature_of(owner), wallet_id.clone(), 1.into(), address_of(Keyring::Bob), 2.into()));
+            }
+            assert_eq!(Multisig::vault_balance(&wallet, 1), 3);
+            assert_eq!(Multisig::vault_balance(&wallet, 0), 10);
+            assert_eq!(balance_of(address_of(Keyring::Bob)), 101);
+        });
+    }
 
-            assert_eq!(bob_balance_before + 1, bob_balance_after);
+    #[test]
+    fn migrate() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::open_vault(signature_of(owner), wallet_id.clone()));
+                assert_ok!(Multisig::add_allowed_destination(signature_of(owner), wallet_id.clone(), address_of(Keyring::Bob)));
+                assert_ok!(Multisig::set_proposal_limits(signature_of(owner), wallet_id.clone(), 3.into(), 2.into(), 1.into()));
+            }
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), Multisig::vault_account(&wallet, 1).into(), 5.into()));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(Multisig::operations(&wallet).len(), 1);
+
+            let new_owners = vec![address_of(Keyring::Bob), address_of(Keyring::Charlie), address_of(Keyring::Dave)];
+            assert_eq!(Multisig::migrate(signature_of(Keyring::Alice), wallet_id.clone(), new_owners.clone(), 4.into(), vec![]),
+                Err("invalid number of signatures"));
+            assert_eq!(Multisig::migrate(signature_of(Keyring::Alice), wallet_id.clone(), new_owners.clone(), 2.into(),
+                vec![address_of(Keyring::Alice)]), Err("mandatory signer isn't owner"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::migrate(signature_of(owner), wallet_id.clone(), new_owners.clone(), 2.into(), vec![address_of(Keyring::Dave)]));
+            }
+
+            let successor = Multisig::successor(&wallet).unwrap();
+            let successor_id: Address = successor.clone().into();
+            assert_eq!(Multisig::signatures_required(&successor), 2);
+            assert_eq!(Multisig::owners(&successor).len(), 3);
+            assert_eq!(Multisig::mandatory(&successor), vec![account_id_of(address_of(Keyring::Dave))]);
+            assert_eq!(Multisig::vault_balance(&wallet, 0), 0);
+            assert_eq!(Multisig::vault_balance(&wallet, 1), 0);
+            assert_eq!(Multisig::vault_balance(&successor, 0), 10);
+            assert_eq!(Multisig::vault_balance(&successor, 1), 5);
+            assert_eq!(Multisig::allowlist(&successor), vec![account_id_of(address_of(Keyring::Bob))]);
+            assert_eq!(Multisig::max_proposals(&successor), 3);
+            assert_eq!(Multisig::max_owner_proposals(&successor), 2);
+            assert_eq!(Multisig::proposal_deposit(&successor), 1);
+            assert_eq!(Multisig::max_proposals(&wallet), 0);
+            assert_eq!(Multisig::proposal_deposit(&wallet), 0);
+
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 3.into()),
+                Err("wallet is migrated"));
+
+            // pending withdrawal is carried over without approvals
+            let operations = Multisig::operations(&successor);
+            assert_eq!(operations.len(), 1);
+            assert_eq!(Multisig::pending(operations[0]).unwrap().wallet, successor);
+            assert_eq!(Multisig::operation_bitmask((successor.clone(), operations[0])), 0);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), successor_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(balance_of(successor_id.clone()), 10);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Dave), successor_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(balance_of(successor_id.clone()), 7);
+            assert!(Multisig::operations(&successor).is_empty());
+        });
+    }
+
+    #[test]
+    fn migrate_delay() {
+        with_externalities(&mut new_test_ext(), || {
+            let owners = vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)];
+            assert_ok!(Multisig::create(signature_of(Keyring::Alice), owners.clone(), 2.into(), vec![address_of(Keyring::Charlie)]));
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+            let wallet = account_id_of(wallet_id.clone());
+            <system::Module<Test>>::set_block_number(1);
+
+            for owner in vec![Keyring::Alice, Keyring::Charlie] {
+                assert_ok!(Multisig::set_delay(signature_of(owner), wallet_id.clone(), 2));
+                assert_ok!(Multisig::set_veto_power(signature_of(owner), wallet_id.clone(), address_of(Keyring::Bob), true));
+            }
+
+            // the mandatory signer has to approve the migration
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::migrate(signature_of(owner), wallet_id.clone(), owners.clone(), 2.into(), vec![]));
+            }
+            assert_eq!(Multisig::queued_migration(&wallet), None);
+            assert_ok!(Multisig::migrate(signature_of(Keyring::Charlie), wallet_id.clone(), owners.clone(), 2.into(), vec![]));
+
+            // and the migration waits for the delay, during which it may be vetoed
+            let migration = Multisig::queued_migration(&wallet).unwrap();
+            assert_eq!(migration.execute_at, 3);
+            assert_eq!(Multisig::successor(&wallet), None);
+            assert_eq!(Multisig::migrate(signature_of(Keyring::Alice), wallet_id.clone(), owners.clone(), 3.into(), vec![]),
+                Err("migration is already queued"));
+            assert_ok!(Multisig::veto(signature_of(Keyring::Bob), wallet_id.clone(), migration.operation));
+            assert_eq!(Multisig::queued_migration(&wallet), None);
+            assert!(Multisig::migrations_at(3).is_empty());
+
+            for owner in vec![Keyring::Alice, Keyring::Charlie] {
+                assert_ok!(Multisig::migrate(signature_of(owner), wallet_id.clone(), owners.clone(), 2.into(), vec![]));
+            }
+            <system::Module<Test>>::set_block_number(3);
+            <Multisig as OnFinalise<u64>>::on_finalise(3);
+            assert_eq!(Multisig::queued_migration(&wallet), None);
+            let successor = Multisig::successor(&wallet).unwrap();
+            assert_eq!(Multisig::owners(&successor).len(), 3);
+            assert!(Multisig::mandatory(&successor).is_empty());
+        });
+    }
+
+    #[test]
+    fn reserve_mode() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_reserve_mode(signature_of(owner), wallet_id.clone(), true));
+                assert_ok!(Multisig::set_lifetime(signature_of(owner), wallet_id.clone(), 3));
+            }
+            <system::Module<Test>>::set_block_number(1);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 6.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+            assert_eq!(Balances::reserved_balance(&wallet), 6);
+
+            // the reserved amount can't be overdrawn
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 5.into()),
+                Err("not enough free funds"));
+            assert_eq!(Multisig::operations(&wallet).len(), 1);
+
+            let bob_balance = balance_of(address_of(Keyring::Bob));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 6.into()));
+            assert_eq!(balance_of(address_of(Keyring::Bob)), bob_balance + 6);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+
+            // cancel
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            let operation = Multisig::operations(&wallet)[0];
+            assert_eq!(Balances::reserved_balance(&wallet), 2);
+            assert_eq!(Multisig::cancel(signature_of(Keyring::Bob), wallet_id.clone(), operation), Err("sender isn't proposer"));
+            assert_ok!(Multisig::cancel(signature_of(Keyring::Alice), wallet_id.clone(), operation));
+            assert_eq!(Multisig::pending(operation), None);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+
+            // expiry
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 2.into()));
+            assert_eq!(Multisig::pending(operation).unwrap().expires_at, 4);
+            <Multisig as OnFinalise<u64>>::on_finalise(3);
+            assert_eq!(Balances::reserved_balance(&wallet), 2);
+            <Multisig as OnFinalise<u64>>::on_finalise(4);
+            assert_eq!(Multisig::pending(operation), None);
+            assert!(Multisig::operations(&wallet).is_empty());
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+        });
+    }
+
+    #[test]
+    fn reserve_existential_deposit() {
+        with_externalities(&mut new_test_ext_with_existential_deposit(5), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 20);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_reserve_mode(signature_of(owner), wallet_id.clone(), true));
+            }
+
+            // the reservation would be burnt, or leave the free balance to be reaped
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 3.into()),
+                Err("amount is below the existential deposit"));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 16.into()),
+                Err("not enough free funds"));
+            assert!(Multisig::operations(&wallet).is_empty());
+
+            // the reserved amount creates the account of a new destination
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 6.into()));
+            assert_eq!(Balances::reserved_balance(&wallet), 6);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Dave), 6.into()));
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 6);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+            assert_eq!(balance_of(wallet_id.clone()), 14);
+        });
+    }
+
+    #[test]
+    fn fee_budget() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            let charlie = account_id_of(address_of(Keyring::Charlie));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_fee_budget(signature_of(owner), wallet_id.clone(), 5.into()));
+            }
+            assert_eq!(Multisig::fee_budget(&wallet), 5);
+
+            <balances::TransactionBaseFee<Test>>::put(2);
+
+            // the wallet pays the fees of the owners who have chosen it only
+            assert_ok!(<Multisig as MakePayment<_>>::make_payment(&charlie, 0));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 8);
+            assert_eq!(Multisig::set_fee_payer(signature_of(Keyring::Dave), Some(wallet_id.clone())), Err("sender isn't owner"));
+            assert_ok!(Multisig::set_fee_payer(signature_of(Keyring::Charlie), Some(wallet_id.clone())));
+
+            // a signature is paid by the wallet
+            assert_ok!(<Multisig as MakePayment<_>>::make_payment(&charlie, 0));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            <Multisig as OnFinalise<u64>>::on_finalise(1);
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 8);
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+            assert_eq!(Multisig::fee_budget(&wallet), 3);
+
+            // other transactions are charged back
+            assert_ok!(<Multisig as MakePayment<_>>::make_payment(&charlie, 0));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 1.into()),
+                Err("sender already signed"));
+            assert_eq!(balance_of(wallet_id.clone()), 6);
+            <Multisig as OnFinalise<u64>>::on_finalise(2);
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 6);
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+            assert_eq!(Multisig::fee_budget(&wallet), 3);
+
+            // the owner pays the fee once the budget doesn't cover it
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_fee_budget(signature_of(owner), wallet_id.clone(), 1.into()));
+            }
+            assert_ok!(<Multisig as MakePayment<_>>::make_payment(&charlie, 0));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 4);
+            assert_eq!(balance_of(wallet_id.clone()), 8);
+            assert_eq!(Multisig::fee_budget(&wallet), 1);
+        });
+    }
+
+    #[test]
+    fn refund_on_execution() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_refund_limit(signature_of(owner), wallet_id.clone(), 1.into()));
+            }
+            assert_eq!(Multisig::refund_limit(&wallet), 1);
+
+            <balances::TransactionBaseFee<Test>>::put(2);
+
+            // nothing is refunded until the operation is executed
+            let alice_balance = balance_of(address_of(Keyring::Alice));
+            let charlie_balance = balance_of(address_of(Keyring::Charlie));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(balance_of(address_of(Keyring::Alice)), alice_balance);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            // refunds are capped by the limit
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 3);
+            assert_eq!(balance_of(address_of(Keyring::Alice)), alice_balance + 1);
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), charlie_balance + 1);
+            assert_eq!(balance_of(wallet_id.clone()), 5);
+
+            // signatures are refunded either from the fee budget or on execution, never both
+            assert_eq!(Multisig::set_fee_budget(signature_of(Keyring::Alice), wallet_id.clone(), 2.into()),
+                Err("refund limit is set"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_refund_limit(signature_of(owner), wallet_id.clone(), 0.into()));
+            }
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_fee_budget(signature_of(owner), wallet_id.clone(), 2.into()));
+            }
+            assert_eq!(Multisig::set_refund_limit(signature_of(Keyring::Alice), wallet_id.clone(), 1.into()),
+                Err("fee budget is set"));
+
+            assert_eq!(balance_of(wallet_id.clone()), 3);
+            assert_eq!(balance_of(address_of(Keyring::Alice)), alice_balance + 2);
+
+            // the execution refund is disabled
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 4);
+            assert_eq!(balance_of(address_of(Keyring::Alice)), alice_balance + 2);
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), charlie_balance + 1);
+            assert_eq!(balance_of(wallet_id.clone()), 2);
+        });
+    }
+
+    #[test]
+    fn proposal_limits() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let alice = account_id_of(address_of(Keyring::Alice));
+            let bob = account_id_of(address_of(Keyring::Bob));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_proposal_limits(signature_of(owner), wallet_id.clone(), 2.into(), 1.into(), 5.into()));
+            }
+            assert!(Multisig::proposed(&wallet).is_empty());
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 1.into()));
+            assert_eq!(Balances::reserved_balance(&alice), 5);
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 2.into()),
+                Err("too many pending operations of sender"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(Balances::reserved_balance(&bob), 5);
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 4.into()),
+                Err("too many pending operations"));
+
+            // management operations are exempt from the wallet cap, but not from the owner cap
+            let charlie = account_id_of(address_of(Keyring::Charlie));
+            assert_ok!(Multisig::set_delay(signature_of(Keyring::Charlie), wallet_id.clone(), 1));
+            assert_eq!(Balances::reserved_balance(&charlie), 5);
+            assert_eq!(Multisig::set_delay(signature_of(Keyring::Charlie), wallet_id.clone(), 2),
+                Err("too many pending operations of sender"));
+            let operations = Multisig::proposed(&wallet);
+            assert_eq!(operations.len(), 3);
+            assert_ok!(Multisig::cancel(signature_of(Keyring::Charlie), wallet_id.clone(), operations[2]));
+            assert_eq!(Balances::reserved_balance(&charlie), 0);
+
+            // the deposit is returned on cancellation
+            let operations = Multisig::proposed(&wallet);
+            assert_eq!(Multisig::cancel(signature_of(Keyring::Bob), wallet_id.clone(), operations[0]), Err("sender isn't proposer"));
+            assert_ok!(Multisig::cancel(signature_of(Keyring::Alice), wallet_id.clone(), operations[0]));
+            assert_eq!(Balances::reserved_balance(&alice), 0);
+
+            // and on execution
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(Balances::reserved_balance(&bob), 0);
+            assert!(Multisig::proposed(&wallet).is_empty());
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+        });
+    }
+
+    #[test]
+    fn approve_by_hash() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 3, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let bob = account_id_of(address_of(Keyring::Bob));
+
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &bob, 3, &[], None, &Default::default());
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::approve(signature_of(owner), wallet_id.clone(), operation, vec![]));
+            }
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 3);
+            assert_eq!(Multisig::pending(operation), None);
+
+            // parameters which don't match the approved hash are a different operation
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 4.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+
+            // the quorum gathered by hash only is executed by any approver revealing the parameters
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &bob, 3, &[], None, &Default::default());
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::approve(signature_of(owner), wallet_id.clone(), operation, vec![]));
+            }
+            assert_eq!(Multisig::approve(signature_of(Keyring::Bob), wallet_id.clone(), operation, vec![]), Err("sender already signed"));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Bob), 3.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+
+            let operation = Multisig::operation_hash(b"set_delay", &wallet, 5u64);
+            assert_ok!(Multisig::approve(signature_of(Keyring::Alice), wallet_id.clone(), operation, vec![]));
+            assert_eq!(Multisig::set_delay(signature_of(Keyring::Alice), wallet_id.clone(), 5), Err("sender already signed"));
+            assert_ok!(Multisig::set_delay(signature_of(Keyring::Charlie), wallet_id.clone(), 5));
+            assert_eq!(Multisig::delay(&wallet), 5);
+        });
+    }
+
+    #[test]
+    fn amend() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 3, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw(signature_of(owner), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            }
+            let operation = Multisig::operations(&wallet)[0];
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 3);
+
+            assert_eq!(Multisig::amend(signature_of(Keyring::Dave), operation, address_of(Keyring::Dave), 4.into()),
+                Err("sender isn't owner"));
+            assert_eq!(Multisig::amend(signature_of(Keyring::Charlie), operation, address_of(Keyring::Dave), 3.into()),
+                Err("operation isn't changed"));
+
+            // an operation approved by hash isn't overwritten
+            let dave = account_id_of(address_of(Keyring::Dave));
+            let approved = Multisig::withdrawal_hash(&wallet, 0, &dave, 5, &[], None, &Default::default());
+            assert_ok!(Multisig::approve(signature_of(Keyring::Alice), wallet_id.clone(), approved, vec![]));
+            assert_eq!(Multisig::amend(signature_of(Keyring::Charlie), operation, address_of(Keyring::Dave), 5.into()),
+                Err("operation already exists"));
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), approved)), 1);
+            assert_ok!(Multisig::amend(signature_of(Keyring::Charlie), operation, address_of(Keyring::Dave), 4.into()));
+
+            assert_eq!(Multisig::pending(operation), None);
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 0);
+
+            let amended = Multisig::operations(&wallet)[0];
+            let pending = Multisig::pending(amended).unwrap();
+            assert_eq!(pending.value, 4);
+            assert_eq!(pending.revision, 1);
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), amended)), 4);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw(signature_of(owner), wallet_id.clone(), address_of(Keyring::Dave), 4.into()));
+            }
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 4);
+            assert!(Multisig::operations(&wallet).is_empty());
+        });
+    }
+
+    #[test]
+    fn memo() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let dave = account_id_of(address_of(Keyring::Dave));
+
+            assert_eq!(Multisig::withdraw_with_memo(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 3.into(), vec![0; MAX_MEMO_LENGTH + 1]), Err("memo is too long"));
+
+            assert_ok!(Multisig::withdraw_with_memo(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 3.into(), b"INV-42".to_vec()));
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, b"INV-42", None, &Default::default());
+            assert_eq!(Multisig::pending(operation).unwrap().memo, b"INV-42".to_vec());
+
+            // the memo is part of the operation
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            assert_eq!(Multisig::approve(signature_of(Keyring::Charlie), wallet_id.clone(), operation, vec![0; MAX_COMMENT_LENGTH + 1]),
+                Err("comment is too long"));
+            assert_ok!(Multisig::approve(signature_of(Keyring::Charlie), wallet_id.clone(), operation, b"checked".to_vec()));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+            assert_ok!(Multisig::withdraw_with_memo(signature_of(Keyring::Charlie), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 3.into(), b"INV-42".to_vec()));
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+            assert_eq!(Multisig::pending(operation), None);
+        });
+    }
+
+    #[test]
+    fn approve_revealed_by_hash() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let dave = account_id_of(address_of(Keyring::Dave));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()),
+                Err("sender already signed"));
+
+            // the quorum is completed by hash, any approver executes the revealed withdrawal
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &Default::default());
+            assert_ok!(Multisig::approve(signature_of(Keyring::Bob), wallet_id.clone(), operation, vec![]));
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+        });
+    }
+
+    #[test]
+    fn reject() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            let operation = Multisig::operations(&wallet)[0];
+
+            assert_ok!(Multisig::reject(signature_of(Keyring::Bob), wallet_id.clone(), operation, b"wrong invoice".to_vec()));
+            assert_eq!(Multisig::reject(signature_of(Keyring::Bob), wallet_id.clone(), operation, vec![]),
+                Err("sender already rejected"));
+            assert_eq!(Multisig::rejections((wallet.clone(), operation)), 2);
+            assert!(Multisig::pending(operation).is_some());
+
+            // an approval withdraws the rejection
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Dave), 4.into()));
+            let other = Multisig::operations(&wallet)[1];
+            assert_ok!(Multisig::reject(signature_of(Keyring::Bob), wallet_id.clone(), other, vec![]));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Dave), 4.into()));
+            assert_eq!(balance_of(wallet_id.clone()), 6);
+            assert_eq!(Multisig::rejections((wallet.clone(), other)), 0);
+
+            // a rejection withdraws the approval, two rejections leave the quorum unreachable
+            assert_ok!(Multisig::reject(signature_of(Keyring::Alice), wallet_id.clone(), operation, vec![]));
+            assert_eq!(Multisig::pending(operation), None);
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 0);
+            assert_eq!(Multisig::rejections((wallet.clone(), operation)), 0);
+            assert!(Multisig::operations(&wallet).is_empty());
+            assert_eq!(Multisig::reject(signature_of(Keyring::Charlie), wallet_id.clone(), operation, vec![]),
+                Err("operation isn't pending"));
+
+            // management operations
+            assert_ok!(Multisig::set_delay(signature_of(Keyring::Alice), wallet_id.clone(), 5));
+            let operation = Multisig::operation_hash(b"set_delay", &wallet, 5u64);
+            for owner in vec![Keyring::Bob, Keyring::Charlie] {
+                assert_ok!(Multisig::reject(signature_of(owner), wallet_id.clone(), operation, vec![]));
+            }
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 0);
+            assert_eq!(Multisig::proposal((wallet.clone(), operation)), None);
+
+            // operations of another wallet can't be rejected
+            let other_id = new_wallet(Keyring::Charlie, vec![Keyring::Dave, Keyring::Charlie], 2, 0);
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            let operation = Multisig::operations(&wallet)[0];
+            assert_eq!(Multisig::reject(signature_of(Keyring::Charlie), other_id.clone(), operation, vec![]),
+                Err("operation isn't pending"));
+            assert!(Multisig::pending(operation).is_some());
+        });
+    }
+
+    #[test]
+    fn reject_by_mandatory_signer() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Multisig::create(signature_of(Keyring::Alice),
+                vec![address_of(Keyring::Alice), address_of(Keyring::Bob), address_of(Keyring::Charlie)],
+                2.into(),
+                vec![address_of(Keyring::Charlie)]));
+            let wallet_id = wallet_id_of(Keyring::Alice, Multisig::global_nonce() - 1);
+            let wallet = account_id_of(wallet_id.clone());
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), wallet_id.clone().into(), 10.into()));
+
+            // the others still gather the quorum, but can't approve without the mandatory signer
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            let operation = Multisig::operations(&wallet)[0];
+            assert_ok!(Multisig::reject(signature_of(Keyring::Charlie), wallet_id.clone(), operation, vec![]));
+            assert_eq!(Multisig::pending(operation), None);
+            assert!(Multisig::operations(&wallet).is_empty());
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+        });
+    }
+
+    #[test]
+    fn dependencies() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let dave = account_id_of(address_of(Keyring::Dave));
+
+            assert_eq!(Multisig::withdraw_after(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 2.into(), vec![], Default::default()), Err("prerequisite doesn't exist"));
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            let prerequisite = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &Default::default());
+            let dependent = Multisig::withdrawal_hash(&wallet, 0, &dave, 2, &[], Some(prerequisite), &Default::default());
+
+            // the approved dependent waits for its prerequisite
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_after(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 2.into(), vec![], prerequisite));
+            }
+            assert!(Multisig::queued(dependent).is_some());
+            assert_eq!(Multisig::prerequisite(dependent), Some(prerequisite));
+            assert_eq!(Multisig::dependents_of(prerequisite), vec![dependent]);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(Multisig::queued(dependent), None);
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 5);
+            assert!(Multisig::operations(&wallet).is_empty());
+            // the record of the execution is consumed by the last dependent
+            assert!(!Multisig::executed((wallet.clone(), prerequisite)));
+            assert!(Multisig::dependents(prerequisite).is_empty());
+
+            // cancelling the prerequisite cancels its dependents
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            let prerequisite = Multisig::withdrawal_hash(&wallet, 0, &dave, 1, &[], None, &Default::default());
+            assert_ok!(Multisig::withdraw_after(signature_of(Keyring::Charlie), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], prerequisite));
+            let dependent = Multisig::withdrawal_hash(&wallet, 0, &dave, 1, &[], Some(prerequisite), &Default::default());
+            assert!(Multisig::pending(dependent).is_some());
+
+            assert_ok!(Multisig::cancel(signature_of(Keyring::Alice), wallet_id.clone(), prerequisite));
+            assert_eq!(Multisig::pending(dependent), None);
+            assert!(Multisig::operations(&wallet).is_empty());
+            assert_eq!(balance_of(wallet_id.clone()), 5);
+
+            // an executed withdrawal is a prerequisite within its wallet only
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            assert_ok!(Multisig::withdraw_after(signature_of(Keyring::Charlie), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], prerequisite));
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Bob), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            assert!(Multisig::executed((wallet.clone(), prerequisite)));
+
+            let other_id = new_wallet(Keyring::Bob, vec![Keyring::Alice, Keyring::Bob], 2, 10);
+            assert_eq!(Multisig::withdraw_after(signature_of(Keyring::Alice), other_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], prerequisite), Err("prerequisite doesn't exist"));
+
+            // the waiting dependent still executes
+            assert_ok!(Multisig::withdraw_after(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], prerequisite));
+            assert_eq!(balance_of(wallet_id.clone()), 3);
+            assert!(!Multisig::executed((wallet.clone(), prerequisite)));
+        });
+    }
+
+    #[test]
+    fn execution_window() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let dave = account_id_of(address_of(Keyring::Dave));
+            <system::Module<Test>>::set_block_number(1);
+
+            // approved before the window opens, executed in the block hook
+            let window = ExecutionWindow { earliest: 5, latest: 0, min_balance: 0 };
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_conditional(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 3.into(), vec![], None, window.clone()));
+            }
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &window);
+            assert_eq!(Multisig::queued(operation).unwrap().execute_at, 5);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
+
+            <system::Module<Test>>::set_block_number(5);
+            <Multisig as OnFinalise<u64>>::on_finalise(5);
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 3);
+
+            // minimum remaining balance, waited for until the window closes
+            let window = ExecutionWindow { earliest: 0, latest: 7, min_balance: 5 };
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_conditional(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 3.into(), vec![], None, window.clone()));
+            }
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &window);
+            assert_eq!(Multisig::queued(operation).unwrap().execute_at, 6);
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+
+            <system::Module<Test>>::set_block_number(6);
+            <Multisig as OnFinalise<u64>>::on_finalise(6);
+            assert_eq!(Multisig::queued(operation).unwrap().execute_at, 7);
+            <system::Module<Test>>::set_block_number(7);
+            <Multisig as OnFinalise<u64>>::on_finalise(7);
+            assert_eq!(Multisig::queued(operation), None);
+            assert_eq!(balance_of(wallet_id.clone()), 7);
+
+            // and executed once it is met
+            let window = ExecutionWindow { earliest: 0, latest: 0, min_balance: 5 };
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_conditional(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 3.into(), vec![], None, window.clone()));
+            }
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &window);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), wallet_id.clone().into(), 1.into()));
+            <system::Module<Test>>::set_block_number(8);
+            <Multisig as OnFinalise<u64>>::on_finalise(8);
+            assert_eq!(Multisig::queued(operation), None);
+            assert_eq!(balance_of(wallet_id.clone()), 5);
+            assert_ok!(Balances::transfer(signature_of(Keyring::Alice), wallet_id.clone().into(), 2.into()));
+
+            let window = ExecutionWindow { earliest: 0, latest: 0, min_balance: 4 };
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_conditional(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 3.into(), vec![], None, window.clone()));
+            }
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+
+            // the pending withdrawal expires once its window closes
+            assert_eq!(Multisig::withdraw_conditional(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], None, ExecutionWindow { earliest: 9, latest: 8, min_balance: 0 }),
+                Err("invalid withdrawal window"));
+            assert_eq!(Multisig::withdraw_conditional(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], None, ExecutionWindow { earliest: 0, latest: 7, min_balance: 0 }),
+                Err("withdrawal window has closed"));
+            assert_eq!(Multisig::withdraw_conditional(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], None, ExecutionWindow { earliest: 0, latest: u64::max_value(), min_balance: 0 }),
+                Err("invalid withdrawal window"));
+            // an unreachable minimum waits until the window closes
+            let unreachable = ExecutionWindow { earliest: 0, latest: 9, min_balance: u64::max_value() };
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::withdraw_conditional(signature_of(owner), wallet_id.clone(), 0.into(),
+                    address_of(Keyring::Dave), 1.into(), vec![], None, unreachable.clone()));
+            }
+            let waiting = Multisig::withdrawal_hash(&wallet, 0, &dave, 1, &[], None, &unreachable);
+            assert_eq!(Multisig::queued(waiting).unwrap().execute_at, 9);
+
+            let window = ExecutionWindow { earliest: 0, latest: 9, min_balance: 0 };
+            assert_ok!(Multisig::withdraw_conditional(signature_of(Keyring::Alice), wallet_id.clone(), 0.into(),
+                address_of(Keyring::Dave), 1.into(), vec![], None, window.clone()));
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 1, &[], None, &window);
+            assert_eq!(Multisig::pending(operation).unwrap().expires_at, 10);
+
+            <system::Module<Test>>::set_block_number(9);
+            <Multisig as OnFinalise<u64>>::on_finalise(9);
+            assert_eq!(Multisig::queued(waiting), None);
+            <system::Module<Test>>::set_block_number(10);
+            <Multisig as OnFinalise<u64>>::on_finalise(10);
+            assert_eq!(Multisig::pending(operation), None);
+            assert!(Multisig::operations(&wallet).is_empty());
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+
+            // withdrawals whose expiry or execution block overflows are rejected before signing
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_delay(signature_of(owner), wallet_id.clone(), u64::max_value()));
+            }
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()),
+                Err("delay is too long"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_lifetime(signature_of(owner), wallet_id.clone(), u64::max_value()));
+            }
+            assert_eq!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 1.into()),
+                Err("lifetime is too long"));
+            assert!(Multisig::operations(&wallet).is_empty());
+        });
+    }
+
+    #[test]
+    fn hash_lock() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let hashlock = <Test as system::Trait>::Hashing::hash(b"secret");
+            <system::Module<Test>>::set_block_number(1);
+
+            assert_eq!(Multisig::lock(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie),
+                11.into(), hashlock, 5), Err("not enough free funds"));
+            assert_eq!(Multisig::lock(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie),
+                4.into(), hashlock, 1), Err("timeout has passed"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::lock(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 4.into(), hashlock, 5));
+            }
+            let lock = Multisig::operation_hash(b"lock", &wallet, (account_id_of(address_of(Keyring::Charlie)), 4u64, hashlock, 5u64));
+            assert_eq!(Multisig::hash_lock(lock).unwrap().value, 4);
+            assert_eq!(balance_of(wallet_id.clone()), 6);
+            assert_eq!(Balances::reserved_balance(&wallet), 4);
+
+            // claim
+            assert_eq!(Multisig::claim(signature_of(Keyring::Charlie), lock, b"guess".to_vec()), Err("invalid preimage"));
+            assert_ok!(Multisig::claim(signature_of(Keyring::Charlie), lock, b"secret".to_vec()));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 14);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+            assert_eq!(Multisig::hash_lock(lock), None);
+            assert_eq!(Multisig::claim(signature_of(Keyring::Charlie), lock, b"secret".to_vec()), Err("lock doesn't exist"));
+
+            // claim by a new account, not while the wallet is frozen
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::lock(signature_of(owner), wallet_id.clone(), address_of(Keyring::Dave), 2.into(), hashlock, 5));
+            }
+            let lock = Multisig::operation_hash(b"lock", &wallet, (account_id_of(address_of(Keyring::Dave)), 2u64, hashlock, 5u64));
+            assert_ok!(Multisig::freeze(signature_of(Keyring::Charlie), wallet_id.clone()));
+            assert_eq!(Multisig::claim(signature_of(Keyring::Dave), lock, b"secret".to_vec()), Err("wallet is frozen"));
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::unfreeze(signature_of(owner), wallet_id.clone()));
+            }
+            assert_ok!(Multisig::claim(signature_of(Keyring::Dave), lock, b"secret".to_vec()));
+            assert_eq!(balance_of(address_of(Keyring::Dave)), 2);
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+
+            // refund after the timeout
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::lock(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 3.into(), hashlock, 5));
+            }
+            let lock = Multisig::operation_hash(b"lock", &wallet, (account_id_of(address_of(Keyring::Charlie)), 3u64, hashlock, 5u64));
+            assert_eq!(balance_of(wallet_id.clone()), 1);
+
+            <system::Module<Test>>::set_block_number(6);
+            assert_eq!(Multisig::claim(signature_of(Keyring::Charlie), lock, b"secret".to_vec()), Err("lock has timed out"));
+            <Multisig as OnFinalise<u64>>::on_finalise(6);
+            assert_eq!(Multisig::hash_lock(lock), None);
+            assert_eq!(balance_of(wallet_id.clone()), 4);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+        });
+    }
+
+    #[test]
+    fn hash_lock_delay() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let charlie = account_id_of(address_of(Keyring::Charlie));
+            let hashlock = <Test as system::Trait>::Hashing::hash(b"secret");
+            <system::Module<Test>>::set_block_number(1);
+
+            assert_eq!(Multisig::lock(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Charlie),
+                4.into(), hashlock, u64::max_value()), Err("invalid timeout"));
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::set_delay(signature_of(owner), wallet_id.clone(), 2));
+                assert_ok!(Multisig::set_veto_power(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), true));
+            }
+
+            // an approved lock waits for the wallet delay and may be vetoed
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::lock(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 4.into(), hashlock, 10));
+            }
+            let lock = Multisig::lock_hash(&wallet, &charlie, 4, hashlock, 10);
+            assert_eq!(Multisig::queued(lock).unwrap().execute_at, 3);
+            assert_eq!(Multisig::hash_lock(lock), None);
+            assert_ok!(Multisig::veto(signature_of(Keyring::Charlie), wallet_id.clone(), lock));
+            assert_eq!(Multisig::queued(lock), None);
+
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::lock(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie), 3.into(), hashlock, 10));
+            }
+            let lock = Multisig::lock_hash(&wallet, &charlie, 3, hashlock, 10);
+            assert_eq!(Balances::reserved_balance(&wallet), 0);
+
+            <system::Module<Test>>::set_block_number(3);
+            <Multisig as OnFinalise<u64>>::on_finalise(3);
+            assert_eq!(Multisig::hash_lock(lock).unwrap().value, 3);
+            assert_eq!(Balances::reserved_balance(&wallet), 3);
+            assert!(Multisig::operations(&wallet).is_empty());
+
+            assert_ok!(Multisig::claim(signature_of(Keyring::Charlie), lock, b"secret".to_vec()));
+            assert_eq!(balance_of(address_of(Keyring::Charlie)), 13);
+        });
+    }
+
+    #[test]
+    fn cancel_by_removed_owner() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Charlie), wallet_id.clone(), address_of(Keyring::Dave), 1.into()));
+            let operation = Multisig::operations(&wallet)[0];
+            for owner in vec![Keyring::Alice, Keyring::Bob] {
+                assert_ok!(Multisig::remove_owner(signature_of(owner), wallet_id.clone(), address_of(Keyring::Charlie)));
+            }
+
+            assert_eq!(Multisig::cancel(signature_of(Keyring::Charlie), wallet_id.clone(), operation), Err("sender isn't owner"));
+            assert!(Multisig::pending(operation).is_some());
+        });
+    }
+
+    #[test]
+    fn approve_through_other_wallet() {
+        with_externalities(&mut new_test_ext(), || {
+            let wallet_id = new_wallet(Keyring::Alice, vec![Keyring::Alice, Keyring::Bob, Keyring::Charlie], 2, 10);
+            let wallet = account_id_of(wallet_id.clone());
+            let other_id = new_wallet(Keyring::Charlie, vec![Keyring::Dave, Keyring::Charlie], 2, 0);
+            let dave = account_id_of(address_of(Keyring::Dave));
+
+            let operation = Multisig::withdrawal_hash(&wallet, 0, &dave, 3, &[], None, &Default::default());
+            assert_eq!(Multisig::approve(signature_of(Keyring::Dave), wallet_id.clone(), operation, vec![]),
+                Err("sender isn't owner"));
+
+            // approvals given through another wallet don't count towards this one
+            for owner in vec![Keyring::Dave, Keyring::Charlie] {
+                assert_ok!(Multisig::approve(signature_of(owner), other_id.clone(), operation, vec![]));
+            }
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 0);
+
+            assert_ok!(Multisig::withdraw(signature_of(Keyring::Alice), wallet_id.clone(), address_of(Keyring::Dave), 3.into()));
+            assert_eq!(Multisig::operation_bitmask((wallet.clone(), operation)), 1);
+            assert_eq!(balance_of(wallet_id.clone()), 10);
         });
     }
 }